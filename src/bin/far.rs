@@ -0,0 +1,433 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use sims_far::image::png_to_bmp;
+use sims_far::{diff, normalize_name, Far, FarWriter, FileKind, ManifestEntry, Pattern};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// List, extract and create The Sims 1 .far files.
+#[derive(Parser)]
+#[command(name = "far", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the files in a far file with their sizes and offsets.
+    List {
+        /// The far file.
+        far: String,
+    },
+    /// Extract files from a far file.
+    Extract {
+        /// The far file.
+        far: String,
+        /// Only extract files whose names match one of these glob patterns, like `**/*.bmp`.
+        patterns: Vec<String>,
+        /// The directory to extract into.
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+        /// Convert images to this format while extracting.
+        #[arg(long, value_enum)]
+        convert: Option<Convert>,
+    },
+    /// Create a far file from the files in a directory.
+    Create {
+        /// The directory to pack.
+        dir: PathBuf,
+        /// The far file to create.
+        far: String,
+        /// Convert PNG files to bitmaps in the format of the bitmap with the same name, apart
+        /// from the extension, in this far file, and pack them under its name. PNG files without
+        /// one become 24-bit bitmaps named like the PNG with a .bmp extension.
+        #[arg(long, value_name = "ORIGINAL")]
+        from_png: Option<String>,
+    },
+    /// Show the header of a far file and check it and its layout for problems.
+    Info {
+        /// The far file.
+        far: String,
+    },
+    /// Show the files added, removed, renamed and modified between two far files.
+    Diff {
+        /// The old far file.
+        old: String,
+        /// The new far file.
+        new: String,
+        /// Print the differences as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Convert {
+    /// PNG with real alpha instead of a magenta color key.
+    Png,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::List { far } => list(&far),
+        Command::Extract {
+            far,
+            patterns,
+            output,
+            convert,
+        } => extract(&far, &patterns, &output, convert),
+        Command::Create { dir, far, from_png } => create(&dir, &far, from_png.as_deref()),
+        Command::Info { far } => info(&far),
+        Command::Diff { old, new, json } => diff_far(&old, &new, json),
+    };
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("far: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn list(path: &str) -> Result<ExitCode, Box<dyn Error>> {
+    let far = Far::new(path)?;
+    println!("{:>10}  {:>10}  name", "size", "offset");
+    for me in &far.manifest.manifest_entries {
+        println!(
+            "{:>10}  {:>10}  {}",
+            me.file_length1, me.file_offset, me.file_name
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn extract(
+    path: &str,
+    patterns: &[String],
+    output: &Path,
+    convert: Option<Convert>,
+) -> Result<ExitCode, Box<dyn Error>> {
+    let far = Far::new(path)?;
+    let patterns = patterns
+        .iter()
+        .map(|p| Pattern::new(p))
+        .collect::<Result<Vec<Pattern>, _>>()?;
+    let mut code = ExitCode::SUCCESS;
+    // extract through the directory tree so directories spelled differently by different file
+    // names end up in one directory
+    for file in far.tree().files() {
+        let Some(me) = file.entry.map(|i| &far.manifest.manifest_entries[i]) else {
+            continue;
+        };
+        if !patterns.is_empty() && !patterns.iter().any(|p| p.matches(&me.file_name)) {
+            continue;
+        }
+        let Some(relative) = entry_path(&file.path) else {
+            eprintln!(
+                "far: skipping {:?}, it points outside the output",
+                me.file_name
+            );
+            code = ExitCode::FAILURE;
+            continue;
+        };
+        let target = output.join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let convert_png = convert == Some(Convert::Png)
+            && match me.kind() {
+                Ok(kind) => kind.is_image(),
+                Err(e) => {
+                    eprintln!(
+                        "far: can't recognize {:?}, extracting it as is: {}",
+                        me.file_name, e
+                    );
+                    false
+                }
+            };
+        if convert_png {
+            let mut png: Vec<u8> = vec![];
+            match far.export_png(me, &mut png) {
+                Ok(()) => {
+                    let target = target.with_extension("png");
+                    fs::write(&target, png)?;
+                    println!("{}", target.display());
+                    continue;
+                }
+                Err(e) => eprintln!(
+                    "far: can't convert {:?}, extracting it as is: {}",
+                    me.file_name, e
+                ),
+            }
+        }
+        fs::write(&target, me.get_bytes()?)?;
+        println!("{}", target.display());
+    }
+    Ok(code)
+}
+
+/// The path to extract a file to, relative to the output directory. Both `/` and `\` separate
+/// directories and empty and `.` components are dropped, so names starting with a separator stay
+/// inside the output. Returns `None` for paths that would escape the output directory.
+fn entry_path(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for part in name.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => return None,
+            // drive letters
+            _ if part.contains(':') => return None,
+            _ => path.push(part),
+        }
+    }
+    (!path.as_os_str().is_empty()).then_some(path)
+}
+
+/// The name to pack a file as: its path relative to `dir`, separated by `/`.
+fn far_name(dir: &Path, file: &Path) -> Result<String, Box<dyn Error>> {
+    let name: Vec<String> = file
+        .strip_prefix(dir)?
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    Ok(name.join("/"))
+}
+
+fn create(dir: &Path, path: &str, from_png: Option<&str>) -> Result<ExitCode, Box<dyn Error>> {
+    let original = from_png.map(Far::new).transpose()?;
+    let mut files: Vec<PathBuf> = vec![];
+    collect_files(dir, &mut files)?;
+    files.sort();
+
+    let mut writer = FarWriter::new();
+    for file in &files {
+        let name = far_name(dir, file)?;
+        let bytes = fs::read(file)?;
+        let is_png = name.to_ascii_lowercase().ends_with(".png");
+        match &original {
+            Some(original) if is_png => {
+                let (name, bytes) = convert_png(original, &name, bytes)?;
+                writer.add(&name, bytes);
+            }
+            _ => {
+                writer.add(&name, bytes);
+            }
+        }
+    }
+    writer.write_to_path(path)?;
+    println!("packed {} files into {}", files.len(), path);
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The name and contents to pack the PNG file `name` as. The PNG is converted to a bitmap in the
+/// format of the file in `original` with the same name apart from the extension, and packed under
+/// that file's name. Fails if that file isn't a bitmap or the PNG can't be written in its format.
+/// PNG files without a file in `original` become 24-bit bitmaps, and PNG files in `original` are
+/// packed as they are.
+fn convert_png(
+    original: &Far,
+    name: &str,
+    png: Vec<u8>,
+) -> Result<(String, Vec<u8>), Box<dyn Error>> {
+    if original.contains(name) {
+        return Ok((name.to_string(), png));
+    }
+    let stem = normalize_name(without_extension(name));
+    let matches: Vec<&ManifestEntry> = original
+        .manifest
+        .manifest_entries
+        .iter()
+        .filter(|me| normalize_name(without_extension(&me.file_name)) == stem)
+        .collect();
+    let me = match matches[..] {
+        [] => {
+            let name = format!("{}.bmp", without_extension(name));
+            return Ok((name, png_to_bmp(&png, None)?));
+        }
+        [me] => me,
+        _ => {
+            let names: Vec<&str> = matches.iter().map(|me| me.file_name.as_str()).collect();
+            return Err(format!("{name:?} could replace any of {names:?}").into());
+        }
+    };
+    let bytes = me.get_bytes()?;
+    let converted = match FileKind::from_bytes(&me.file_name, &bytes) {
+        FileKind::Bmp => png_to_bmp(&png, Some(&bytes)),
+        kind => {
+            return Err(format!(
+                "can't convert {name:?} to {kind:?} to replace {:?}",
+                me.file_name
+            )
+            .into())
+        }
+    };
+    let converted = converted
+        .map_err(|e| format!("can't convert {name:?} to replace {:?}: {e}", me.file_name))?;
+    Ok((me.file_name.clone(), converted))
+}
+
+/// `name` without the extension of its last component.
+fn without_extension(name: &str) -> &str {
+    let file = name.rfind(['/', '\\']).map_or(0, |i| i + 1);
+    match name[file..].rfind('.') {
+        Some(i) => &name[..file + i],
+        None => name,
+    }
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Box<dyn Error>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn info(path: &str) -> Result<ExitCode, Box<dyn Error>> {
+    let (far, diagnostics) = Far::open_lenient(path)?;
+    println!("signature:       {}", far.signature);
+    println!("version:         {}", far.version);
+    println!("variant:         {:?}", far.variant);
+    println!("manifest offset: {}", far.manifest_offset);
+    println!("number of files: {}", far.manifest.number_of_files);
+    println!("readable files:  {}", far.manifest.manifest_entries.len());
+    let report = far.verify()?;
+    let problems = diagnostics.len() + report.problems.len();
+    if problems == 0 {
+        println!("no problems found");
+        return Ok(ExitCode::SUCCESS);
+    }
+    println!("{} problems found:", problems);
+    for diagnostic in &diagnostics {
+        match diagnostic.entry {
+            Some(entry) => println!("  entry {}: {}", entry, diagnostic.error),
+            None => println!("  {}", diagnostic.error),
+        }
+    }
+    for problem in &report.problems {
+        println!("  {}", problem);
+    }
+    Ok(ExitCode::FAILURE)
+}
+
+fn diff_far(old: &str, new: &str, json: bool) -> Result<ExitCode, Box<dyn Error>> {
+    let d = diff(&Far::new(old)?, &Far::new(new)?)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&d)?);
+    } else {
+        for name in &d.added {
+            println!("A  {}", name);
+        }
+        for name in &d.removed {
+            println!("D  {}", name);
+        }
+        for renamed in &d.renamed {
+            println!("R  {} -> {}", renamed.from, renamed.to);
+        }
+        for modified in &d.modified {
+            println!(
+                "M  {} ({} -> {} bytes)",
+                modified.name, modified.old_length, modified.new_length
+            );
+        }
+    }
+    // exit like diff(1): 1 when the far files differ
+    if d.is_empty() {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::from(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_path() {
+        assert_eq!(
+            entry_path("Buttons\\ok.bmp"),
+            Some(Path::new("Buttons").join("ok.bmp"))
+        );
+        assert_eq!(entry_path("/abs"), Some(PathBuf::from("abs")));
+        assert_eq!(entry_path("./a//b"), Some(Path::new("a").join("b")));
+        assert_eq!(entry_path("../x"), None);
+        assert_eq!(entry_path("..\\x"), None);
+        assert_eq!(entry_path("a/../../x"), None);
+        assert_eq!(entry_path("C:x"), None);
+        assert_eq!(entry_path("C:\\Windows\\x"), None);
+        assert_eq!(entry_path("/"), None);
+    }
+
+    #[test]
+    fn test_far_name() {
+        let dir = Path::new("UIGraphics");
+        let file = dir.join("Buttons").join("ok.bmp");
+        assert_eq!(far_name(dir, &file).unwrap(), "Buttons/ok.bmp");
+        assert!(far_name(dir, Path::new("elsewhere/ok.bmp")).is_err());
+    }
+
+    #[test]
+    fn test_convert_png() {
+        use sims_far::image::{bmp, RgbaImage};
+
+        let mut image = RgbaImage::new(2, 1);
+        image.put_pixel(0, 0, [10, 20, 30, 255]);
+        let mut png: Vec<u8> = vec![];
+        image.write_png(&mut png).unwrap();
+        let tga = vec![
+            0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 24, 0, 1, 2, 3,
+        ];
+        let mut writer = FarWriter::new();
+        writer.add("UI/Frame.tga", tga);
+        writer.add(
+            "UI\\btn.bmp",
+            bmp::encode(&image, &bmp::EncodeOptions::default()).unwrap(),
+        );
+        writer.add("UI/readme.txt", b"text".to_vec());
+        writer.add("UI/logo.png", b"png".to_vec());
+        writer.add("dup.bmp", b"BM".to_vec());
+        writer.add("dup.tga", b"".to_vec());
+        let mut buf: Vec<u8> = vec![];
+        writer.write_to(&mut buf).unwrap();
+        let original = Far::from_bytes(&buf).unwrap();
+
+        // extracted with --convert png and packed again, unchanged
+        let me = original.get("UI/btn.bmp").unwrap();
+        let mut exported: Vec<u8> = vec![];
+        original.export_png(me, &mut exported).unwrap();
+        let (name, bytes) = convert_png(&original, "UI/btn.png", exported).unwrap();
+        assert_eq!(name, "UI\\btn.bmp");
+        assert_eq!(bytes, me.get_bytes().unwrap());
+
+        // a Targa image can't be written, so it isn't silently replaced by a bitmap
+        let me = original.get("UI/Frame.tga").unwrap();
+        let mut exported: Vec<u8> = vec![];
+        original.export_png(me, &mut exported).unwrap();
+        assert!(convert_png(&original, "UI/frame.png", exported).is_err());
+
+        // new PNG files become 24-bit bitmaps, PNG files in the original are kept
+        let (name, bytes) = convert_png(&original, "UI/new.png", png.clone()).unwrap();
+        assert_eq!(name, "UI/new.bmp");
+        assert_eq!(bmp::read_header(&bytes).unwrap().bits_per_pixel, 24);
+        let (name, bytes) = convert_png(&original, "UI/logo.png", b"png".to_vec()).unwrap();
+        assert_eq!((name.as_str(), &bytes[..]), ("UI/logo.png", &b"png"[..]));
+
+        assert!(convert_png(&original, "UI/readme.png", png.clone()).is_err());
+        assert!(convert_png(&original, "dup.png", png).is_err());
+    }
+
+    #[test]
+    fn test_without_extension() {
+        assert_eq!(without_extension("UI/frame.tga"), "UI/frame");
+        assert_eq!(without_extension("UI.d/frame"), "UI.d/frame");
+        assert_eq!(without_extension("a\\b.c.bmp"), "a\\b.c");
+    }
+}
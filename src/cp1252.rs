@@ -0,0 +1,57 @@
+/// The characters for bytes 0x80 to 0x9F. Windows-1252 leaves 0x81, 0x8D, 0x8F, 0x90 and 0x9D
+/// undefined; like browsers, they are mapped to the C1 control characters of the same value so
+/// every byte round-trips.
+const HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+/// Decode a filename. Names that are valid UTF-8 are kept as they are, everything else is decoded
+/// as Windows-1252, the code page used by the localized releases of the game.
+pub(crate) fn decode(bytes: &[u8]) -> String {
+    if let Ok(s) = std::str::from_utf8(bytes) {
+        return s.to_string();
+    }
+    bytes
+        .iter()
+        .map(|b| match b {
+            0x80..=0x9F => HIGH[(b - 0x80) as usize],
+            _ => *b as char,
+        })
+        .collect()
+}
+
+/// Encode a filename as Windows-1252, falling back to UTF-8 if it has characters that
+/// Windows-1252 can't represent.
+pub(crate) fn encode(s: &str) -> Vec<u8> {
+    let encoded: Option<Vec<u8>> = s
+        .chars()
+        .map(|c| match c as u32 {
+            0x00..=0x7F | 0xA0..=0xFF => Some(c as u8),
+            _ => HIGH.iter().position(|h| *h == c).map(|i| 0x80 + i as u8),
+        })
+        .collect();
+    encoded.unwrap_or_else(|| s.as_bytes().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"test.bmp"), "test.bmp");
+        assert_eq!(decode("Größe.bmp".as_bytes()), "Größe.bmp");
+        assert_eq!(decode(b"Gr\xF6\xDFe.bmp"), "Größe.bmp");
+        assert_eq!(decode(b"\x80\x81.bmp"), "\u{20AC}\u{0081}.bmp");
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode("Größe.bmp"), b"Gr\xF6\xDFe.bmp");
+        assert_eq!(encode("\u{20AC}.bmp"), b"\x80.bmp");
+        assert_eq!(encode("日本.bmp"), "日本.bmp".as_bytes());
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{normalize_name, Far, FarError, ManifestEntry};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hasher;
+use std::io;
+use std::io::{Read, Write};
+
+/// The differences between two far files, found by [`diff`]. Names are matched like
+/// [`Far::get`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FarDiff {
+    /// Files only in the new far file, in its manifest order.
+    pub added: Vec<String>,
+    /// Files only in the old far file, in its manifest order.
+    pub removed: Vec<String>,
+    /// Files with the same contents under a new name.
+    pub renamed: Vec<Renamed>,
+    /// Files in both far files with different contents.
+    pub modified: Vec<Modified>,
+}
+
+/// A file that was renamed without changing its contents.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Renamed {
+    /// The name in the old far file.
+    pub from: String,
+    /// The name in the new far file.
+    pub to: String,
+}
+
+/// A file whose contents changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Modified {
+    /// The name in the new far file.
+    pub name: String,
+    /// The length in the old far file.
+    pub old_length: u32,
+    /// The length in the new far file.
+    pub new_length: u32,
+}
+
+impl FarDiff {
+    /// Whether the far files have the same files.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.renamed.is_empty()
+            && self.modified.is_empty()
+    }
+}
+
+/// Compare the files in `old` and `new`. Files with the same name are compared by length and then
+/// byte for byte. A file that disappears from `old` and appears in `new` under another name with
+/// the same contents is reported as renamed; candidates are found by a hash of their decompressed
+/// contents and then compared byte for byte.
+pub fn diff(old: &Far, new: &Far) -> Result<FarDiff, FarError> {
+    let mut result = FarDiff::default();
+
+    // files in both
+    let mut added: Vec<&ManifestEntry> = vec![];
+    for me in &new.manifest.manifest_entries {
+        let Some(old_me) = old.get(&me.file_name) else {
+            added.push(me);
+            continue;
+        };
+        if old_me.file_length1 != me.file_length1 || !same_contents(old_me, me)? {
+            result.modified.push(Modified {
+                name: me.file_name.clone(),
+                old_length: old_me.file_length1,
+                new_length: me.file_length1,
+            });
+        }
+    }
+
+    // files only in new, by length and hash, to pair up with the files only in old
+    let mut added_by_content: HashMap<(u32, u64), Vec<&ManifestEntry>> = HashMap::new();
+    for me in &added {
+        added_by_content
+            .entry((me.file_length1, content_hash(me)?))
+            .or_default()
+            .push(me);
+    }
+
+    let mut renamed_to: HashSet<String> = HashSet::new();
+    for me in &old.manifest.manifest_entries {
+        if new.contains(&me.file_name) {
+            continue;
+        }
+        let key = (me.file_length1, content_hash(me)?);
+        let mut to: Option<&ManifestEntry> = None;
+        if let Some(candidates) = added_by_content.get_mut(&key) {
+            // the hash only narrows the candidates down, the contents decide
+            for i in 0..candidates.len() {
+                if same_contents(me, candidates[i])? {
+                    to = Some(candidates.remove(i));
+                    break;
+                }
+            }
+        }
+        match to {
+            Some(to) => {
+                renamed_to.insert(normalize_name(&to.file_name));
+                result.renamed.push(Renamed {
+                    from: me.file_name.clone(),
+                    to: to.file_name.clone(),
+                });
+            }
+            None => result.removed.push(me.file_name.clone()),
+        }
+    }
+
+    result.added = added
+        .iter()
+        .filter(|me| !renamed_to.contains(&normalize_name(&me.file_name)))
+        .map(|me| me.file_name.clone())
+        .collect();
+    Ok(result)
+}
+
+/// A hash of the decompressed contents, streamed so large files aren't read into memory.
+fn content_hash(me: &ManifestEntry) -> Result<u64, FarError> {
+    let mut hasher = HashWriter(DefaultHasher::new());
+    io::copy(&mut me.open()?, &mut hasher)?;
+    Ok(hasher.0.finish())
+}
+
+/// Whether the decompressed contents are the same, streamed in chunks.
+fn same_contents(a: &ManifestEntry, b: &ManifestEntry) -> Result<bool, FarError> {
+    let (mut a, mut b) = (a.open()?, b.open()?);
+    let mut buf_a = [0u8; 8192];
+    let mut buf_b = [0u8; 8192];
+    loop {
+        let n = read_full(&mut a, &mut buf_a)?;
+        if n != read_full(&mut b, &mut buf_b)? || buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+        if n == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Fill `buf` as far as the reader allows, returning the number of bytes read.
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..])? {
+            0 => break,
+            read => n += read,
+        }
+    }
+    Ok(n)
+}
+
+struct HashWriter(DefaultHasher);
+
+impl Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::test_far as far;
+
+    #[test]
+    fn test_diff() {
+        let old = far(&[
+            ("same.bmp", b"same"),
+            ("grown.bmp", b"abc"),
+            ("changed.bmp", b"abcd"),
+            ("old_name.bmp", b"moved"),
+            ("gone.bmp", b"gone"),
+        ]);
+        let new = far(&[
+            ("SAME.bmp", b"same"),
+            ("grown.bmp", b"abcdef"),
+            ("changed.bmp", b"abce"),
+            ("new_name.bmp", b"moved"),
+            ("new.bmp", b"new"),
+        ]);
+
+        let d = diff(&old, &new).unwrap();
+        assert_eq!(d.added, ["new.bmp"]);
+        assert_eq!(d.removed, ["gone.bmp"]);
+        assert_eq!(
+            d.renamed,
+            [Renamed {
+                from: "old_name.bmp".to_string(),
+                to: "new_name.bmp".to_string()
+            }]
+        );
+        let modified: Vec<(&str, u32, u32)> = d
+            .modified
+            .iter()
+            .map(|m| (m.name.as_str(), m.old_length, m.new_length))
+            .collect();
+        assert_eq!(modified, [("grown.bmp", 3, 6), ("changed.bmp", 4, 4)]);
+
+        assert!(diff(&old, &old).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_same_contents() {
+        let long: Vec<u8> = (0..20000).map(|i| i as u8).collect();
+        let mut changed = long.clone();
+        changed[19999] ^= 1;
+        let far = far(&[("a", &long), ("b", &long), ("c", &changed), ("d", b"")]);
+        let me = &far.manifest.manifest_entries;
+        assert!(same_contents(&me[0], &me[1]).unwrap());
+        assert!(!same_contents(&me[0], &me[2]).unwrap());
+        assert!(!same_contents(&me[0], &me[3]).unwrap());
+        assert!(same_contents(&me[3], &me[3]).unwrap());
+    }
+}
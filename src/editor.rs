@@ -0,0 +1,363 @@
+use crate::writer::{to_u32, too_large, write_manifest, ManifestRecord};
+use crate::{cp1252, normalize_name, Far, FarError, FarVariant, FarWriter, ManifestEntry};
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::SeekFrom::{End, Start};
+use std::io::{BufWriter, Read, Seek, Write};
+use std::process;
+
+/// Edits a copy of a far file's manifest. Files can be added, replaced, renamed and removed, and
+/// [`FarEditor::commit`] writes a new far file with the offsets and manifest laid out again.
+///
+/// Names are matched like [`Far::get`]. Unchanged files are read from the original far file when
+/// committing, so it must stay readable until then.
+#[derive(Clone, Debug)]
+pub struct FarEditor {
+    entries: Vec<EditorEntry>,
+    variant: FarVariant,
+    /// Whether the far file being edited is a version three archive.
+    far3: bool,
+    /// The signature, manifest offset and length of the far file being edited, checked by
+    /// [`FarEditor::commit_append`] before it writes anything.
+    signature: String,
+    manifest_offset: u32,
+    file_length: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+struct EditorEntry {
+    file_name: String,
+    file_name_bytes: Vec<u8>,
+    contents: Contents,
+}
+
+#[derive(Clone, Debug)]
+enum Contents {
+    Original(ManifestEntry),
+    New(Vec<u8>),
+}
+
+impl Far {
+    /// Start editing the far file. See [`FarEditor`].
+    pub fn edit(&self) -> FarEditor {
+        FarEditor::new(self)
+    }
+}
+
+impl FarEditor {
+    /// Start editing `far`. Version three archives are committed as 1b archives.
+    pub fn new(far: &Far) -> FarEditor {
+        FarEditor {
+            entries: far
+                .manifest
+                .manifest_entries
+                .iter()
+                .map(|me| EditorEntry {
+                    file_name: me.file_name.clone(),
+                    file_name_bytes: me.file_name_bytes.clone(),
+                    contents: Contents::Original(me.clone()),
+                })
+                .collect(),
+            variant: match far.variant {
+                FarVariant::V1a => FarVariant::V1a,
+                _ => FarVariant::V1b,
+            },
+            far3: far.variant == FarVariant::V3,
+            signature: far.signature.clone(),
+            manifest_offset: far.manifest_offset,
+            file_length: far.source.lock().and_then(|mut f| f.seek(End(0))).ok(),
+        }
+    }
+
+    /// The file names, in the order they will be written.
+    pub fn file_names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|entry| entry.file_name.as_str())
+    }
+
+    /// Add a new file at the end of the archive. Fails if a file called `file_name` exists.
+    pub fn add(&mut self, file_name: &str, bytes: Vec<u8>) -> Result<&mut FarEditor, FarError> {
+        if self.position(file_name).is_some() {
+            return Err(FarError::EntryExists {
+                name: file_name.to_string(),
+            });
+        }
+        self.entries.push(EditorEntry {
+            file_name: file_name.to_string(),
+            file_name_bytes: cp1252::encode(file_name),
+            contents: Contents::New(bytes),
+        });
+        Ok(self)
+    }
+
+    /// Replace the contents of `file_name`, keeping its position and name.
+    pub fn replace(&mut self, file_name: &str, bytes: Vec<u8>) -> Result<&mut FarEditor, FarError> {
+        let i = self.find(file_name)?;
+        self.entries[i].contents = Contents::New(bytes);
+        Ok(self)
+    }
+
+    /// Rename `from` to `to`, keeping its position and contents. Fails if a different file called
+    /// `to` exists.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<&mut FarEditor, FarError> {
+        let i = self.find(from)?;
+        if self.position(to).is_some_and(|j| j != i) {
+            return Err(FarError::EntryExists {
+                name: to.to_string(),
+            });
+        }
+        self.entries[i].file_name = to.to_string();
+        self.entries[i].file_name_bytes = cp1252::encode(to);
+        Ok(self)
+    }
+
+    /// Remove `file_name` from the archive.
+    pub fn remove(&mut self, file_name: &str) -> Result<&mut FarEditor, FarError> {
+        let i = self.find(file_name)?;
+        self.entries.remove(i);
+        Ok(self)
+    }
+
+    /// Write the edited archive to `w`.
+    pub fn commit<W: Write>(&self, w: &mut W) -> Result<(), FarError> {
+        self.writer()?.write_to(w)
+    }
+
+    /// Write the edited archive to `path`. The archive is written to a temporary file next to
+    /// `path` first and then moved over it, so `path` can be the far file being edited and is
+    /// never left half written.
+    pub fn commit_to_path(&self, path: &str) -> Result<(), FarError> {
+        let writer = self.writer()?;
+        let temp_path = format!("{}.{}.tmp", path, process::id());
+        let result = writer
+            .write_to_path(&temp_path)
+            .and_then(|_| Ok(fs::rename(&temp_path, path)?));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result
+    }
+
+    /// Update the far file at `path`, which must be the file being edited, without rewriting the
+    /// files that haven't changed. Fails without writing anything if the signature, manifest
+    /// offset or length of `path` differ from the far file being edited. New and replaced files
+    /// are appended to the end of the far file followed by a new manifest, then the manifest
+    /// offset in the header is patched to point at it. Replaced files and old manifests are left
+    /// behind as dead space; use [`Far::compact`] to reclaim it.
+    ///
+    /// If writing fails before the header is patched, the far file still reads as it did before.
+    /// Version three archives can't be appended to.
+    pub fn commit_append(&self, path: &str) -> Result<(), FarError> {
+        if self.far3 {
+            return Err(FarError::FileError(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "appending to version three far files is not supported",
+            )));
+        }
+        let mut f = OpenOptions::new().read(true).write(true).open(path)?;
+        let file_length = f.seek(End(0))?;
+
+        // check the far file is the one being edited
+        let mut header = [0u8; 16];
+        f.seek(Start(0))?;
+        f.read_exact(&mut header)?;
+        let manifest_offset = u32::from_le_bytes([header[12], header[13], header[14], header[15]]);
+        if String::from_utf8_lossy(&header[..8]) != self.signature
+            || manifest_offset != self.manifest_offset
+            || Some(file_length) != self.file_length
+        {
+            return Err(FarError::FileError(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{path} is not the far file being edited, or it has changed since"),
+            )));
+        }
+        let mut offset = to_u32(file_length as usize)?;
+        f.seek(Start(file_length))?;
+
+        // append new files
+        let mut w = BufWriter::new(&mut f);
+        let mut records: Vec<ManifestRecord> = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            let record = match &entry.contents {
+                Contents::Original(me) => ManifestRecord {
+                    file_name: &entry.file_name_bytes,
+                    file_length1: me.file_length1,
+                    file_length2: me.file_length2,
+                    file_offset: me.file_offset,
+                },
+                Contents::New(bytes) => {
+                    w.write_all(bytes)?;
+                    let file_length = to_u32(bytes.len())?;
+                    let record = ManifestRecord {
+                        file_name: &entry.file_name_bytes,
+                        file_length1: file_length,
+                        file_length2: file_length,
+                        file_offset: offset,
+                    };
+                    offset = offset.checked_add(file_length).ok_or_else(too_large)?;
+                    record
+                }
+            };
+            records.push(record);
+        }
+
+        // append the manifest
+        let manifest_offset = offset;
+        write_manifest(&mut w, self.variant, &records)?;
+        w.flush()?;
+        drop(w);
+        f.sync_data()?;
+
+        // point the header at the new manifest
+        f.seek(Start(12))?;
+        f.write_all(&manifest_offset.to_le_bytes())?;
+        f.sync_data()?;
+        Ok(())
+    }
+
+    /// Read every file into a FarWriter.
+    fn writer(&self) -> Result<FarWriter, FarError> {
+        let mut writer = FarWriter::new();
+        writer.variant(self.variant);
+        for entry in &self.entries {
+            let bytes = match &entry.contents {
+                Contents::Original(me) => me.get_bytes()?,
+                Contents::New(bytes) => bytes.clone(),
+            };
+            writer.add_raw(entry.file_name_bytes.clone(), bytes);
+        }
+        Ok(writer)
+    }
+
+    fn position(&self, file_name: &str) -> Option<usize> {
+        let normalized = normalize_name(file_name);
+        self.entries
+            .iter()
+            .position(|entry| normalize_name(&entry.file_name) == normalized)
+    }
+
+    fn find(&self, file_name: &str) -> Result<usize, FarError> {
+        self.position(file_name)
+            .ok_or_else(|| FarError::EntryNotFound {
+                name: file_name.to_string(),
+            })
+    }
+}
+
+impl Far {
+    /// Rewrite the far file at `path` with the files packed together, dropping the dead space
+    /// left by [`FarEditor::commit_append`].
+    pub fn compact(path: &str) -> Result<(), FarError> {
+        Far::new(path)?.edit().commit_to_path(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::test_far;
+
+    #[test]
+    fn test_edit() {
+        let far = Far::new("test.far").unwrap();
+        let mut editor = far.edit();
+        editor.add("Buttons/Btn_ok.bmp", b"ok".to_vec()).unwrap();
+        editor.add("readme.txt", b"hello".to_vec()).unwrap();
+        editor
+            .replace("Buttons\\BTN_OK.bmp", b"okay".to_vec())
+            .unwrap();
+        editor.rename("test.bmp", "renamed.bmp").unwrap();
+        editor.remove("README.TXT").unwrap();
+        assert!(editor.add("renamed.bmp", vec![]).is_err());
+        assert!(editor.rename("renamed.bmp", "buttons/btn_ok.bmp").is_err());
+        assert!(editor.remove("missing.bmp").is_err());
+
+        let mut buf: Vec<u8> = vec![];
+        editor.commit(&mut buf).unwrap();
+        let edited = Far::from_bytes(&buf).unwrap();
+        let names: Vec<&str> = edited
+            .manifest
+            .manifest_entries
+            .iter()
+            .map(|me| me.file_name.as_str())
+            .collect();
+        assert_eq!(names, ["renamed.bmp", "Buttons/Btn_ok.bmp"]);
+        assert_eq!(
+            edited.get("renamed.bmp").unwrap().get_bytes().unwrap(),
+            far.manifest.manifest_entries[0].get_bytes().unwrap()
+        );
+        let me = edited.get("buttons/btn_ok.bmp").unwrap();
+        assert_eq!(me.file_offset, 160);
+        assert_eq!(me.get_bytes().unwrap(), b"okay");
+    }
+
+    #[test]
+    fn test_commit_append() {
+        let path = std::env::temp_dir().join(format!("sims-far-append-{}.far", process::id()));
+        let path = path.to_str().unwrap();
+        fs::copy("test.far", path).unwrap();
+        let original = fs::read("test.far").unwrap();
+
+        let far = Far::new(path).unwrap();
+        let mut editor = far.edit();
+        editor.add("a.txt", b"abcd".to_vec()).unwrap();
+        editor.rename("test.bmp", "renamed.bmp").unwrap();
+        editor.commit_append(path).unwrap();
+
+        // the original files and manifest are untouched
+        let appended = fs::read(path).unwrap();
+        assert_eq!(&appended[16..original.len()], &original[16..]);
+        let edited = Far::new(path).unwrap();
+        assert_eq!(edited.manifest_offset as usize, original.len() + 4);
+        let renamed = edited.get("renamed.bmp").unwrap();
+        assert_eq!(renamed.file_offset, 16);
+        assert_eq!(renamed.get_bytes().unwrap(), &original[16..160]);
+        assert_eq!(edited.get("a.txt").unwrap().get_bytes().unwrap(), b"abcd");
+
+        Far::compact(path).unwrap();
+        let compacted = Far::new(path).unwrap();
+        assert_eq!(compacted.manifest_offset, 164);
+        assert_eq!(
+            compacted.get("a.txt").unwrap().get_bytes().unwrap(),
+            b"abcd"
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_commit_append_other_file() {
+        let path = std::env::temp_dir().join(format!("sims-far-other-{}.far", process::id()));
+        let path = path.to_str().unwrap();
+        fs::copy("test.far", path).unwrap();
+        let original = fs::read(path).unwrap();
+
+        // a far file with a different manifest offset
+        let other = test_far(&[("a.txt", b"abcd")]);
+        assert!(other.edit().commit_append(path).is_err());
+
+        // the same far file, changed since it was opened
+        let far = Far::from_bytes(&original).unwrap();
+        let editor = far.edit();
+        editor.commit_append(path).unwrap();
+        assert!(editor.commit_append(path).is_err());
+        assert_eq!(Far::new(path).unwrap().manifest.number_of_files, 1);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_commit_to_path() {
+        let path = std::env::temp_dir().join(format!("sims-far-editor-{}.far", process::id()));
+        let path = path.to_str().unwrap();
+        fs::copy("test.far", path).unwrap();
+
+        let far = Far::new(path).unwrap();
+        let mut editor = far.edit();
+        editor.add("a.txt", b"abcd".to_vec()).unwrap();
+        editor.commit_to_path(path).unwrap();
+
+        let edited = Far::new(path).unwrap();
+        assert_eq!(edited.manifest.number_of_files, 2);
+        assert_eq!(edited.get("a.txt").unwrap().get_bytes().unwrap(), b"abcd");
+        fs::remove_file(path).unwrap();
+    }
+}
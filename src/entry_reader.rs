@@ -0,0 +1,149 @@
+use crate::source::Source;
+use crate::{decompress, Far, FarError, ManifestEntry};
+use std::io;
+use std::io::SeekFrom::Start;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+/// Reads the contents of one file in a far file without loading all of it into memory. Reads and
+/// seeks are bounded to the file, so reading past its end returns no bytes.
+///
+/// Compressed files in version three archives are decompressed in full when the reader is opened,
+/// since RefPack streams can't be seeked.
+pub struct EntryReader {
+    inner: Inner,
+}
+
+enum Inner {
+    Stored {
+        source: Source,
+        start: u64,
+        length: u64,
+        pos: u64,
+    },
+    Decompressed(Cursor<Vec<u8>>),
+}
+
+impl EntryReader {
+    fn stored(me: &ManifestEntry) -> EntryReader {
+        EntryReader {
+            inner: Inner::Stored {
+                source: me.source.clone(),
+                start: me.file_offset as u64,
+                length: me.stored_length() as u64,
+                pos: 0,
+            },
+        }
+    }
+
+    /// The number of bytes that can be read from the start of the file.
+    pub fn len(&self) -> u64 {
+        match &self.inner {
+            Inner::Stored { length, .. } => *length,
+            Inner::Decompressed(cursor) => cursor.get_ref().len() as u64,
+        }
+    }
+
+    /// Whether the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Read for EntryReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            Inner::Stored {
+                source,
+                start,
+                length,
+                pos,
+            } => {
+                let remaining = length.saturating_sub(*pos);
+                let n = (buf.len() as u64).min(remaining) as usize;
+                if n == 0 {
+                    return Ok(0);
+                }
+                let mut f = source.lock()?;
+                f.seek(Start(*start + *pos))?;
+                let n = f.read(&mut buf[..n])?;
+                *pos += n as u64;
+                Ok(n)
+            }
+            Inner::Decompressed(cursor) => cursor.read(buf),
+        }
+    }
+}
+
+impl Seek for EntryReader {
+    fn seek(&mut self, from: SeekFrom) -> io::Result<u64> {
+        match &mut self.inner {
+            Inner::Stored { length, pos, .. } => {
+                let new_pos = match from {
+                    Start(offset) => Some(offset),
+                    SeekFrom::End(offset) => length.checked_add_signed(offset),
+                    SeekFrom::Current(offset) => pos.checked_add_signed(offset),
+                };
+                let Some(new_pos) = new_pos else {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "invalid seek to a negative or overflowing position",
+                    ));
+                };
+                *pos = new_pos;
+                Ok(new_pos)
+            }
+            Inner::Decompressed(cursor) => cursor.seek(from),
+        }
+    }
+}
+
+impl ManifestEntry {
+    /// Open the file for streaming. Compressed files are decompressed.
+    pub fn open(&self) -> Result<EntryReader, FarError> {
+        if self.is_compressed() {
+            let stored = self.get_stored_bytes()?;
+            return Ok(EntryReader {
+                inner: Inner::Decompressed(Cursor::new(decompress(&stored)?)),
+            });
+        }
+        Ok(EntryReader::stored(self))
+    }
+
+    /// Open the file for streaming without decompressing it.
+    pub fn open_stored(&self) -> EntryReader {
+        EntryReader::stored(self)
+    }
+}
+
+impl Far {
+    /// Open `entry` for streaming. See [`ManifestEntry::open`].
+    pub fn open_entry(&self, entry: &ManifestEntry) -> Result<EntryReader, FarError> {
+        entry.open()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_entry() {
+        let bytes = std::fs::read("test.far").unwrap();
+        let far = Far::from_bytes(&bytes).unwrap();
+        let mut reader = far.open_entry(&far.manifest.manifest_entries[0]).unwrap();
+        assert_eq!(reader.len(), 144);
+
+        let mut buf: [u8; 2] = [0x00; 2];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"BM");
+
+        reader.seek(SeekFrom::End(-4)).unwrap();
+        let mut rest: Vec<u8> = vec![];
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, &bytes[156..160]);
+
+        reader.seek(SeekFrom::Current(10)).unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+        assert!(reader.seek(SeekFrom::Current(-1000)).is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use crate::{normalize_name, Far, FarError, ManifestEntry};
+
+/// A compiled glob pattern for matching file names. Patterns and names are both normalized with
+/// [`normalize_name`], so matching is case-insensitive and `\` and `/` are the same separator.
+///
+/// - `?` matches one character other than a separator.
+/// - `*` matches any run of characters other than a separator.
+/// - `**` matches any run of characters, including separators. `a/**/b` matches `a/b` too.
+/// - `[abc]`, `[a-z]` and `[!a-z]` (or `[^a-z]`) match one character in, or not in, the class.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    pattern: String,
+    tokens: Vec<Token>,
+}
+
+#[derive(Clone, Debug)]
+enum Token {
+    Char(char),
+    Any,
+    Star,
+    DoubleStar,
+    /// `**/`, matching nothing or any run of characters ending in a separator.
+    Dirs,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Pattern {
+    /// Compile `pattern`. Fails if a character class isn't closed.
+    pub fn new(pattern: &str) -> Result<Pattern, FarError> {
+        let chars: Vec<char> = normalize_name(pattern).chars().collect();
+        let mut tokens: Vec<Token> = vec![];
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '?' => tokens.push(Token::Any),
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    let starts_component = i == 0 || chars[i - 1] == '/';
+                    if starts_component && chars.get(i + 2) == Some(&'/') {
+                        tokens.push(Token::Dirs);
+                        i += 2;
+                    } else {
+                        tokens.push(Token::DoubleStar);
+                        i += 1;
+                        // collapse runs of stars
+                        while chars.get(i + 1) == Some(&'*') {
+                            i += 1;
+                        }
+                    }
+                }
+                '*' => tokens.push(Token::Star),
+                '[' => {
+                    let (token, end) = parse_class(&chars, i).ok_or_else(|| {
+                        FarError::PatternError(format!("unclosed character class in {pattern:?}"))
+                    })?;
+                    tokens.push(token);
+                    i = end;
+                }
+                c => tokens.push(Token::Char(c)),
+            }
+            i += 1;
+        }
+        Ok(Pattern {
+            pattern: pattern.to_string(),
+            tokens,
+        })
+    }
+
+    /// The pattern as it was written.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Whether `name` matches the pattern.
+    pub fn matches(&self, name: &str) -> bool {
+        let name: Vec<char> = normalize_name(name).chars().collect();
+        // failed[t * (name.len() + 1) + n] remembers that tokens[t..] can't match name[n..], which
+        // keeps patterns with many stars from backtracking exponentially
+        let mut failed = vec![false; (self.tokens.len() + 1) * (name.len() + 1)];
+        match_from(&self.tokens, &name, 0, 0, &mut failed)
+    }
+}
+
+/// Parse the character class starting at `chars[start]`, which is `[`. Returns the token and the
+/// index of the closing `]`.
+fn parse_class(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let mut i = start + 1;
+    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges: Vec<(char, char)> = vec![];
+    // a `]` straight after the opening bracket is part of the class
+    let first = i;
+    loop {
+        let c = *chars.get(i)?;
+        if c == ']' && i != first {
+            return Some((Token::Class { negated, ranges }, i));
+        }
+        if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|c| *c != ']') {
+            ranges.push((c, chars[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+}
+
+fn match_from(tokens: &[Token], name: &[char], t: usize, n: usize, failed: &mut [bool]) -> bool {
+    let key = t * (name.len() + 1) + n;
+    if failed[key] {
+        return false;
+    }
+    let matched = match tokens.get(t) {
+        None => n == name.len(),
+        Some(Token::Char(c)) => {
+            name.get(n) == Some(c) && match_from(tokens, name, t + 1, n + 1, failed)
+        }
+        Some(Token::Any) => {
+            name.get(n).is_some_and(|c| *c != '/') && match_from(tokens, name, t + 1, n + 1, failed)
+        }
+        Some(Token::Class { negated, ranges }) => {
+            name.get(n).is_some_and(|c| {
+                *c != '/' && ranges.iter().any(|(lo, hi)| lo <= c && c <= hi) != *negated
+            }) && match_from(tokens, name, t + 1, n + 1, failed)
+        }
+        Some(Token::Star) => {
+            let end = name[n..]
+                .iter()
+                .position(|c| *c == '/')
+                .map_or(name.len(), |p| n + p);
+            (n..=end).any(|i| match_from(tokens, name, t + 1, i, failed))
+        }
+        Some(Token::DoubleStar) => {
+            (n..=name.len()).any(|i| match_from(tokens, name, t + 1, i, failed))
+        }
+        Some(Token::Dirs) => {
+            match_from(tokens, name, t + 1, n, failed)
+                || (n..name.len())
+                    .filter(|i| name[*i] == '/')
+                    .any(|i| match_from(tokens, name, t + 1, i + 1, failed))
+        }
+    };
+    if !matched {
+        failed[key] = true;
+    }
+    matched
+}
+
+impl Far {
+    /// The manifest entries whose file names match the glob `pattern`. See [`Pattern`] for the
+    /// syntax.
+    pub fn entries_matching(
+        &self,
+        pattern: &str,
+    ) -> Result<impl Iterator<Item = &ManifestEntry>, FarError> {
+        let pattern = Pattern::new(pattern)?;
+        Ok(self
+            .manifest
+            .manifest_entries
+            .iter()
+            .filter(move |me| pattern.matches(&me.file_name)))
+    }
+
+    /// The manifest entries for which `predicate` returns true.
+    pub fn filter<P>(&self, predicate: P) -> impl Iterator<Item = &ManifestEntry>
+    where
+        P: FnMut(&&ManifestEntry) -> bool,
+    {
+        self.manifest.manifest_entries.iter().filter(predicate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::test_far;
+
+    #[test]
+    fn test_matches() {
+        let bmp = Pattern::new("*.bmp").unwrap();
+        assert!(bmp.matches("test.BMP"));
+        assert!(!bmp.matches("Buttons/test.bmp"));
+
+        let all_bmp = Pattern::new("**/*.bmp").unwrap();
+        assert!(all_bmp.matches("test.bmp"));
+        assert!(all_bmp.matches("Buttons\\Dialogs\\test.bmp"));
+
+        let buttons = Pattern::new("buttons/**").unwrap();
+        assert!(buttons.matches("Buttons/Dialogs/frame.tga"));
+        assert!(!buttons.matches("Cursors/arrow.cur"));
+
+        let nested = Pattern::new("a/**/b/*.bmp").unwrap();
+        assert!(nested.matches("a/b/c.bmp"));
+        assert!(nested.matches("a/x/y/b/c.bmp"));
+        assert!(!nested.matches("a/xb/c.bmp"));
+
+        let class = Pattern::new("btn_[a-c]?.bmp").unwrap();
+        assert!(class.matches("Btn_B1.bmp"));
+        assert!(!class.matches("btn_d1.bmp"));
+        let negated = Pattern::new("btn_[!a-c]?.bmp").unwrap();
+        assert!(negated.matches("btn_d1.bmp"));
+        assert!(!negated.matches("btn_a1.bmp"));
+
+        assert!(Pattern::new("btn_[a-c.bmp").is_err());
+    }
+
+    #[test]
+    fn test_entries_matching() {
+        let far = test_far(&[
+            ("Buttons\\Btn_ok.bmp", &[0x00]),
+            ("Buttons\\frame.tga", b""),
+            ("test.bmp", b""),
+        ]);
+
+        let names: Vec<&str> = far
+            .entries_matching("buttons/*.bmp")
+            .unwrap()
+            .map(|me| me.file_name.as_str())
+            .collect();
+        assert_eq!(names, ["Buttons\\Btn_ok.bmp"]);
+
+        assert_eq!(far.filter(|me| me.file_length1 == 0).count(), 2);
+    }
+}
@@ -0,0 +1,348 @@
+use crate::{normalize_name, Far, FarError, ManifestEntry};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+/// An algorithm for hashing file contents. Each algorithm is behind the feature of the same name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HashAlgorithm {
+    /// CRC-32 (IEEE), a four-byte checksum that is fast to compute.
+    #[cfg(feature = "crc32")]
+    Crc32,
+    /// SHA-256, a 32-byte cryptographic hash.
+    #[cfg(feature = "sha256")]
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// The number of bytes in a digest.
+    pub fn digest_length(self) -> usize {
+        match self {
+            #[cfg(feature = "crc32")]
+            HashAlgorithm::Crc32 => 4,
+            #[cfg(feature = "sha256")]
+            HashAlgorithm::Sha256 => 32,
+        }
+    }
+
+    fn hasher(self) -> Hasher {
+        match self {
+            #[cfg(feature = "crc32")]
+            HashAlgorithm::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+            #[cfg(feature = "sha256")]
+            HashAlgorithm::Sha256 => Hasher::Sha256(<sha2::Sha256 as sha2::Digest>::new()),
+        }
+    }
+}
+
+/// The hash of a file's contents.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Digest(Vec<u8>);
+
+impl Digest {
+    /// The bytes of the digest. CRC-32 checksums are big-endian, the way they are usually
+    /// written.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Parse a digest written as hexadecimal.
+    pub fn from_hex(hex: &str) -> Option<Digest> {
+        if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+            return None;
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()
+            .map(Digest)
+    }
+}
+
+/// Lowercase hexadecimal.
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+enum Hasher {
+    #[cfg(feature = "crc32")]
+    Crc32(crc32fast::Hasher),
+    #[cfg(feature = "sha256")]
+    Sha256(sha2::Sha256),
+}
+
+impl Hasher {
+    fn finish(self) -> Digest {
+        match self {
+            #[cfg(feature = "crc32")]
+            Hasher::Crc32(hasher) => Digest(hasher.finalize().to_be_bytes().to_vec()),
+            #[cfg(feature = "sha256")]
+            Hasher::Sha256(hasher) => Digest(sha2::Digest::finalize(hasher).to_vec()),
+        }
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            #[cfg(feature = "crc32")]
+            Hasher::Crc32(hasher) => hasher.update(buf),
+            #[cfg(feature = "sha256")]
+            Hasher::Sha256(hasher) => sha2::Digest::update(hasher, buf),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hash everything `reader` produces, returning the digest and the number of bytes read.
+fn hash_reader<R: io::Read>(
+    algorithm: HashAlgorithm,
+    reader: &mut R,
+) -> Result<(Digest, u64), FarError> {
+    let mut hasher = algorithm.hasher();
+    let len = io::copy(reader, &mut hasher)?;
+    Ok((hasher.finish(), len))
+}
+
+impl ManifestEntry {
+    /// Hash the contents of the file, streaming it from the far file instead of reading it into
+    /// memory. Compressed files are hashed after decompressing, so a file hashes the same in
+    /// every far file and once extracted.
+    pub fn hash(&self, algorithm: HashAlgorithm) -> Result<Digest, FarError> {
+        let (digest, len) = hash_reader(algorithm, &mut self.open()?)?;
+        if len != self.file_length1 as u64 {
+            return Err(FarError::FileError(io::ErrorKind::UnexpectedEof.into()));
+        }
+        Ok(digest)
+    }
+}
+
+impl Far {
+    /// Hash every file, keyed by its path in [`Far::tree`], which is where the files end up when
+    /// extracted. If the manifest has the same name more than once the first entry wins.
+    pub fn hash_all(&self, algorithm: HashAlgorithm) -> Result<BTreeMap<String, Digest>, FarError> {
+        let mut digests: BTreeMap<String, Digest> = BTreeMap::new();
+        for file in self.tree().files() {
+            if let Some(i) = file.entry {
+                digests.insert(
+                    file.path,
+                    self.manifest.manifest_entries[i].hash(algorithm)?,
+                );
+            }
+        }
+        Ok(digests)
+    }
+}
+
+/// A sidecar list of file hashes, written one file per line as the hexadecimal digest, two
+/// spaces and the path, like `sha256sum` writes them. Lists of SHA-256 digests can be checked
+/// against an extracted directory with `sha256sum -c`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HashList {
+    /// The algorithm the digests were made with.
+    pub algorithm: HashAlgorithm,
+    /// Paths, separated by `/`, to their digests.
+    pub digests: BTreeMap<String, Digest>,
+}
+
+/// A file that doesn't match a [`HashList`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HashMismatch {
+    /// The file is in the list but not in the far file or directory.
+    Missing(String),
+    /// The file's contents have a different digest.
+    Changed {
+        path: String,
+        expected: Digest,
+        actual: Digest,
+    },
+    /// The file is in the far file but not in the list.
+    Unlisted(String),
+}
+
+impl HashList {
+    /// Hash every file in `far`. See [`Far::hash_all`].
+    pub fn from_far(far: &Far, algorithm: HashAlgorithm) -> Result<HashList, FarError> {
+        Ok(HashList {
+            algorithm,
+            digests: far.hash_all(algorithm)?,
+        })
+    }
+
+    /// Read a hash list written by [`HashList::write_to`]. Blank lines are skipped. Fails if a
+    /// line isn't a digest made with `algorithm` followed by a path.
+    pub fn read_from<R: BufRead>(r: R, algorithm: HashAlgorithm) -> Result<HashList, FarError> {
+        let mut digests: BTreeMap<String, Digest> = BTreeMap::new();
+        for (number, line) in r.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            // sha256sum marks files hashed in binary mode with a star
+            let parsed = line
+                .split_once(' ')
+                .and_then(|(hex, path)| Some((Digest::from_hex(hex)?, path)))
+                .filter(|(digest, _)| digest.0.len() == algorithm.digest_length())
+                .and_then(|(digest, path)| {
+                    let path = path.strip_prefix([' ', '*'])?;
+                    (!path.is_empty()).then_some((digest, path))
+                });
+            let Some((digest, path)) = parsed else {
+                return Err(FarError::HashListError(format!(
+                    "line {} is not a {:?} digest and a path",
+                    number + 1,
+                    algorithm
+                )));
+            };
+            digests.insert(path.to_string(), digest);
+        }
+        Ok(HashList { algorithm, digests })
+    }
+
+    /// Read a hash list from the file at `path`.
+    pub fn read_from_path(path: &str, algorithm: HashAlgorithm) -> Result<HashList, FarError> {
+        HashList::read_from(io::BufReader::new(File::open(path)?), algorithm)
+    }
+
+    /// Write the list, sorted by path.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<(), FarError> {
+        for (path, digest) in &self.digests {
+            writeln!(w, "{}  {}", digest, path)?;
+        }
+        Ok(())
+    }
+
+    /// Write the list to the file at `path`.
+    pub fn write_to_path(&self, path: &str) -> Result<(), FarError> {
+        let mut w = io::BufWriter::new(File::create(path)?);
+        self.write_to(&mut w)?;
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Check the files in `far` against the list. Paths are matched like [`Far::get`]. Returns
+    /// the files that don't match, so an empty list means the far file matches.
+    pub fn verify_far(&self, far: &Far) -> Result<Vec<HashMismatch>, FarError> {
+        let mut mismatches: Vec<HashMismatch> = vec![];
+        for (path, expected) in &self.digests {
+            match far.get(path) {
+                Some(me) => self.check(path, expected, me.hash(self.algorithm)?, &mut mismatches),
+                None => mismatches.push(HashMismatch::Missing(path.clone())),
+            }
+        }
+        let listed: HashSet<String> = self.digests.keys().map(|p| normalize_name(p)).collect();
+        for file in far.tree().files() {
+            if !listed.contains(&normalize_name(&file.path)) {
+                mismatches.push(HashMismatch::Unlisted(file.path));
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// Check the files extracted into `dir` against the list. Files in the directory that aren't
+    /// in the list are ignored. Returns the files that don't match.
+    pub fn verify_dir<P: AsRef<Path>>(&self, dir: P) -> Result<Vec<HashMismatch>, FarError> {
+        let mut mismatches: Vec<HashMismatch> = vec![];
+        for (path, expected) in &self.digests {
+            let file = path
+                .split(['/', '\\'])
+                .fold(dir.as_ref().to_path_buf(), |file, part| file.join(part));
+            let mut f = match File::open(&file) {
+                Ok(f) => f,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    mismatches.push(HashMismatch::Missing(path.clone()));
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let (actual, _) = hash_reader(self.algorithm, &mut f)?;
+            self.check(path, expected, actual, &mut mismatches);
+        }
+        Ok(mismatches)
+    }
+
+    fn check(
+        &self,
+        path: &str,
+        expected: &Digest,
+        actual: Digest,
+        mismatches: &mut Vec<HashMismatch>,
+    ) {
+        if *expected != actual {
+            mismatches.push(HashMismatch::Changed {
+                path: path.to_string(),
+                expected: expected.clone(),
+                actual,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::test_far;
+
+    fn far() -> Far {
+        test_far(&[("Buttons\\ok.bmp", b"abc"), ("test.bmp", b"")])
+    }
+
+    #[cfg(feature = "crc32")]
+    #[test]
+    fn test_crc32() {
+        let far = far();
+        let digest = far
+            .get("buttons/ok.bmp")
+            .unwrap()
+            .hash(HashAlgorithm::Crc32);
+        assert_eq!(digest.unwrap().to_string(), "352441c2");
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn test_sha256() {
+        let digests = far().hash_all(HashAlgorithm::Sha256).unwrap();
+        let paths: Vec<&str> = digests.keys().map(|p| p.as_str()).collect();
+        assert_eq!(paths, ["Buttons/ok.bmp", "test.bmp"]);
+        assert_eq!(
+            digests["Buttons/ok.bmp"].to_string(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn test_hash_list() {
+        let far = far();
+        let list = HashList::from_far(&far, HashAlgorithm::Sha256).unwrap();
+        let mut buf: Vec<u8> = vec![];
+        list.write_to(&mut buf).unwrap();
+        let read = HashList::read_from(&buf[..], HashAlgorithm::Sha256).unwrap();
+        assert_eq!(read, list);
+        assert!(read.verify_far(&far).unwrap().is_empty());
+        assert!(HashList::read_from(&b"abc  test.bmp"[..], HashAlgorithm::Sha256).is_err());
+
+        let dir = std::env::temp_dir().join(format!("sims-far-hash-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("Buttons")).unwrap();
+        std::fs::write(dir.join("Buttons").join("ok.bmp"), b"abd").unwrap();
+        let mismatches = list.verify_dir(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            matches!(&mismatches[0], HashMismatch::Changed { path, .. } if path == "Buttons/ok.bmp")
+        );
+        assert_eq!(mismatches[1], HashMismatch::Missing("test.bmp".to_string()));
+    }
+}
@@ -0,0 +1,118 @@
+//! Decoders for the images stored in far files.
+
+pub mod bmp;
+#[cfg(feature = "image")]
+mod png;
+pub mod tga;
+
+#[cfg(feature = "image")]
+pub use png::png_to_bmp;
+
+use crate::{FarError, FileKind};
+
+/// The color the game draws as transparent in UI bitmaps.
+pub const MAGENTA: [u8; 3] = [255, 0, 255];
+
+/// The largest image decoded, in pixels, so a corrupt header can't ask for a huge allocation.
+pub(crate) const MAX_PIXELS: u64 = 1 << 26;
+
+/// An image with four 8-bit channels per pixel, red, green, blue and alpha, stored row by row
+/// from the top left.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RgbaImage {
+    /// The width in pixels.
+    pub width: u32,
+    /// The height in pixels.
+    pub height: u32,
+    /// `width * height * 4` bytes of pixel data.
+    pub pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    /// Create a fully transparent black image.
+    pub fn new(width: u32, height: u32) -> RgbaImage {
+        RgbaImage {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    /// The pixel at column `x` and row `y`, counting from the top left.
+    pub fn get_pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = self.offset(x, y);
+        [
+            self.pixels[i],
+            self.pixels[i + 1],
+            self.pixels[i + 2],
+            self.pixels[i + 3],
+        ]
+    }
+
+    /// Set the pixel at column `x` and row `y`, counting from the top left.
+    pub fn put_pixel(&mut self, x: u32, y: u32, pixel: [u8; 4]) {
+        let i = self.offset(x, y);
+        self.pixels[i..i + 4].copy_from_slice(&pixel);
+    }
+
+    /// Make every pixel with the color `key` fully transparent, keeping its color.
+    pub fn apply_color_key(&mut self, key: [u8; 3]) {
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            if pixel[..3] == key {
+                pixel[3] = 0;
+            }
+        }
+    }
+
+    fn offset(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height, "pixel out of bounds");
+        (y as usize * self.width as usize + x as usize) * 4
+    }
+}
+
+/// Decode the image in `bytes`, recognizing its format with [`FileKind::from_bytes`]. Bitmaps
+/// are decoded with magenta made transparent.
+pub fn decode(name: &str, bytes: &[u8]) -> Result<RgbaImage, FarError> {
+    match FileKind::from_bytes(name, bytes) {
+        FileKind::Bmp => bmp::decode(bytes),
+        FileKind::Tga => tga::decode(bytes),
+        kind => Err(image_error(&format!(
+            "{name:?} is not an image, it is {kind:?}"
+        ))),
+    }
+}
+
+/// Check the dimensions of an image before allocating it.
+pub(crate) fn check_dimensions(width: u64, height: u64) -> Result<(), FarError> {
+    if width == 0 || height == 0 || width * height > MAX_PIXELS {
+        return Err(image_error(&format!(
+            "unsupported image dimensions {}x{}",
+            width, height
+        )));
+    }
+    Ok(())
+}
+
+pub(crate) fn image_error(message: &str) -> FarError {
+    FarError::ImageError(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Far;
+
+    #[test]
+    fn test_decode() {
+        let far = Far::new("test.far").unwrap();
+        let bytes = far.get("test.bmp").unwrap().get_bytes().unwrap();
+        let image = decode("test.bmp", &bytes).unwrap();
+        assert_eq!(image.get_pixel(0, 0), [5, 5, 5, 255]);
+
+        let tga = [
+            0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 24, 0, 1, 2, 3,
+        ];
+        assert_eq!(decode("a.tga", &tga).unwrap().pixels, [3, 2, 1, 255]);
+        assert!(decode("a.txt", b"text").is_err());
+    }
+}
@@ -0,0 +1,757 @@
+//! Windows bitmaps, the format of most of UIGraphics.far.
+//!
+//! Palettized (1, 4 and 8-bit), RLE4, RLE8, 16, 24 and 32-bit bitmaps are decoded, with or
+//! without BI_BITFIELDS color masks, from files with any header version from the OS/2 core
+//! header to the V5 header.
+
+use super::{check_dimensions, image_error, RgbaImage, MAGENTA};
+use crate::FarError;
+use std::collections::HashMap;
+
+/// How the pixel data of a bitmap is stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// Uncompressed, BI_RGB.
+    Rgb,
+    /// 8-bit run-length encoded, BI_RLE8.
+    Rle8,
+    /// 4-bit run-length encoded, BI_RLE4.
+    Rle4,
+    /// Uncompressed with explicit color masks, BI_BITFIELDS or BI_ALPHABITFIELDS.
+    Bitfields,
+}
+
+/// The fields of a bitmap's headers that describe its pixels.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BmpHeader {
+    /// The width in pixels.
+    pub width: u32,
+    /// The height in pixels.
+    pub height: u32,
+    /// Whether the first row of pixel data is the top row. Most bitmaps are stored bottom-up.
+    pub top_down: bool,
+    /// The number of bits per pixel: 1, 4, 8, 16, 24 or 32.
+    pub bits_per_pixel: u16,
+    /// How the pixel data is stored.
+    pub compression: Compression,
+    /// The colors of palettized bitmaps as red, green and blue. Empty for bitmaps with more than
+    /// eight bits per pixel.
+    pub palette: Vec<[u8; 3]>,
+    /// The red, green, blue and alpha masks of bitmaps with more than eight bits per pixel. When
+    /// the bitmap doesn't have masks these are the defaults for its depth, with no alpha.
+    pub masks: [u32; 4],
+    /// The offset of the pixel data from the start of the file.
+    pub data_offset: u32,
+}
+
+/// Options for decoding a bitmap.
+#[derive(Clone, Debug)]
+pub struct DecodeOptions {
+    /// Pixels of this color are made fully transparent. Defaults to [`MAGENTA`], the color the
+    /// game uses. `None` keeps the colors of the bitmap as they are.
+    pub color_key: Option<[u8; 3]>,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> DecodeOptions {
+        DecodeOptions {
+            color_key: Some(MAGENTA),
+        }
+    }
+}
+
+/// Options for encoding a bitmap.
+#[derive(Clone, Debug)]
+pub struct EncodeOptions {
+    /// The number of bits per pixel: 1, 4, 8, 16, 24 or 32. Defaults to 24.
+    pub bits_per_pixel: u16,
+    /// The palette of bitmaps with eight or fewer bits per pixel. When `None` the palette is
+    /// made from the colors in the image, which fails if there are too many. Colors that aren't
+    /// in the palette are written as the nearest color that is, but encoding fails if the image
+    /// has transparent pixels and the color key isn't in the palette.
+    pub palette: Option<Vec<[u8; 3]>>,
+    /// The red, green, blue and alpha masks of 16, 24 and 32-bit bitmaps. When set the bitmap is
+    /// written with BI_BITFIELDS, and with a V3 header when there is an alpha mask. When `None`
+    /// 16-bit bitmaps are written as 5-5-5 and 32-bit bitmaps without alpha.
+    pub masks: Option<[u32; 4]>,
+    /// Fully transparent pixels are written in this color. Defaults to [`MAGENTA`]. `None`
+    /// writes them in their own color.
+    pub color_key: Option<[u8; 3]>,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> EncodeOptions {
+        EncodeOptions {
+            bits_per_pixel: 24,
+            palette: None,
+            masks: None,
+            color_key: Some(MAGENTA),
+        }
+    }
+}
+
+impl EncodeOptions {
+    /// Options for writing a bitmap in the format of the bitmap with `header`, with the same
+    /// depth, palette and color masks. Fails for run-length encoded bitmaps, which can't be
+    /// encoded.
+    pub fn like(header: &BmpHeader) -> Result<EncodeOptions, FarError> {
+        match header.compression {
+            Compression::Rle8 | Compression::Rle4 => Err(image_error(&format!(
+                "encoding {:?} bitmaps is not supported",
+                header.compression
+            ))),
+            compression => Ok(EncodeOptions {
+                bits_per_pixel: header.bits_per_pixel,
+                palette: (!header.palette.is_empty()).then(|| header.palette.clone()),
+                masks: (compression == Compression::Bitfields).then_some(header.masks),
+                color_key: Some(MAGENTA),
+            }),
+        }
+    }
+}
+
+/// Read the headers of the bitmap in `bytes`.
+pub fn read_header(bytes: &[u8]) -> Result<BmpHeader, FarError> {
+    if !bytes.starts_with(b"BM") {
+        return Err(image_error("missing BM signature"));
+    }
+    let data_offset = u32_at(bytes, 10)?;
+    let header_size = u32_at(bytes, 14)?;
+
+    // read dimensions
+    let (width, height, bits_per_pixel, compression, colors_used, palette_entry) = match header_size
+    {
+        // OS/2 core header
+        12 => (
+            u16_at(bytes, 18)? as i32,
+            u16_at(bytes, 20)? as i32,
+            u16_at(bytes, 24)?,
+            0,
+            0,
+            3,
+        ),
+        40.. => (
+            u32_at(bytes, 18)? as i32,
+            u32_at(bytes, 22)? as i32,
+            u16_at(bytes, 28)?,
+            u32_at(bytes, 30)?,
+            u32_at(bytes, 46)?,
+            4,
+        ),
+        _ => {
+            return Err(image_error(&format!(
+                "unsupported header size {header_size}"
+            )))
+        }
+    };
+    let compression = match (compression, bits_per_pixel) {
+        (0, 1 | 4 | 8 | 16 | 24 | 32) => Compression::Rgb,
+        (1, 8) => Compression::Rle8,
+        (2, 4) => Compression::Rle4,
+        (3 | 6, 16 | 24 | 32) => Compression::Bitfields,
+        _ => {
+            return Err(image_error(&format!(
+                "unsupported compression {compression} with {bits_per_pixel} bits per pixel"
+            )))
+        }
+    };
+    check_dimensions(width.unsigned_abs() as u64, height.unsigned_abs() as u64)?;
+
+    // read color masks, which follow a 40 byte header and are part of the later ones
+    let mut masks = match bits_per_pixel {
+        16 => [0x7C00, 0x03E0, 0x001F, 0],
+        _ => [0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0],
+    };
+    let mut palette_offset = 14 + header_size as usize;
+    if compression == Compression::Bitfields {
+        let (offset, count) = match header_size {
+            40 => (
+                palette_offset,
+                if compression_is_alpha(bytes) { 4 } else { 3 },
+            ),
+            52 => (54, 3),
+            _ => (54, 4),
+        };
+        for (i, mask) in masks.iter_mut().enumerate().take(count) {
+            *mask = u32_at(bytes, offset + i * 4)?;
+        }
+        if header_size == 40 {
+            palette_offset += count * 4;
+        }
+    }
+
+    // read palette
+    let mut palette: Vec<[u8; 3]> = vec![];
+    if bits_per_pixel <= 8 {
+        let max = 1 << bits_per_pixel;
+        let count = match colors_used as usize {
+            0 => max,
+            n => n.min(max),
+        };
+        for i in 0..count {
+            let offset = palette_offset + i * palette_entry;
+            let entry = bytes
+                .get(offset..offset + 3)
+                .ok_or_else(|| image_error("truncated palette"))?;
+            palette.push([entry[2], entry[1], entry[0]]);
+        }
+    }
+
+    Ok(BmpHeader {
+        width: width.unsigned_abs(),
+        height: height.unsigned_abs(),
+        top_down: height < 0,
+        bits_per_pixel,
+        compression,
+        palette,
+        masks,
+        data_offset,
+    })
+}
+
+/// Decode the bitmap in `bytes`, making magenta pixels transparent.
+pub fn decode(bytes: &[u8]) -> Result<RgbaImage, FarError> {
+    decode_with_options(bytes, &DecodeOptions::default())
+}
+
+/// Decode the bitmap in `bytes` with the given options.
+pub fn decode_with_options(bytes: &[u8], options: &DecodeOptions) -> Result<RgbaImage, FarError> {
+    let header = read_header(bytes)?;
+    let data = bytes
+        .get(header.data_offset as usize..)
+        .ok_or_else(|| image_error("pixel data offset is past the end of the file"))?;
+    check_data_length(data, &header)?;
+    let mut image = RgbaImage::new(header.width, header.height);
+    match header.compression {
+        Compression::Rle8 | Compression::Rle4 => decode_rle(data, &header, &mut image)?,
+        Compression::Rgb | Compression::Bitfields => decode_rows(data, &header, &mut image)?,
+    }
+    if let Some(key) = options.color_key {
+        image.apply_color_key(key);
+    }
+    Ok(image)
+}
+
+/// Encode `image` as an uncompressed bitmap stored bottom-up, with a 40 byte header, or a 56
+/// byte V3 header when there is an alpha mask.
+pub fn encode(image: &RgbaImage, options: &EncodeOptions) -> Result<Vec<u8>, FarError> {
+    let bits = options.bits_per_pixel as usize;
+    if !matches!(bits, 1 | 4 | 8 | 16 | 24 | 32) {
+        return Err(image_error(&format!("unsupported bits per pixel {bits}")));
+    }
+    let masks = match options.masks {
+        Some(masks) if bits < 16 || masks.iter().any(|m| bits < 32 && *m >> bits != 0) => {
+            return Err(image_error(&format!(
+                "color masks {masks:08X?} don't fit in {bits} bits per pixel"
+            )))
+        }
+        Some(masks) => masks,
+        None if bits == 16 => [0x7C00, 0x03E0, 0x001F, 0],
+        None => [0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0],
+    };
+    let colors: Vec<[u8; 3]> = image
+        .pixels
+        .chunks_exact(4)
+        .map(|p| match options.color_key {
+            Some(key) if p[3] == 0 => key,
+            _ => [p[0], p[1], p[2]],
+        })
+        .collect();
+
+    // pick the palette
+    let max_colors = 1 << bits.min(8);
+    let palette = match (&options.palette, bits) {
+        (_, 16..) => vec![],
+        (Some(palette), _) => palette.clone(),
+        (None, _) => {
+            let mut palette: Vec<[u8; 3]> = vec![];
+            for color in &colors {
+                if !palette.contains(color) {
+                    palette.push(*color);
+                }
+                if palette.len() > max_colors {
+                    return Err(image_error(&format!(
+                        "the image has more than {max_colors} colors"
+                    )));
+                }
+            }
+            palette
+        }
+    };
+    if bits <= 8 && (palette.is_empty() || palette.len() > max_colors) {
+        return Err(image_error(&format!(
+            "a {bits}-bit bitmap needs a palette of 1 to {max_colors} colors"
+        )));
+    }
+    if let Some(key) = options.color_key {
+        let transparent = image.pixels.chunks_exact(4).any(|p| p[3] == 0);
+        if bits <= 8 && transparent && !palette.contains(&key) {
+            return Err(image_error(&format!(
+                "the image has transparent pixels but the palette has no {key:?} for them"
+            )));
+        }
+    }
+
+    // write headers
+    let width = image.width as usize;
+    let stride = (width * bits).div_ceil(32) * 4;
+    let (header_size, mask_count, compression) = match options.masks {
+        Some(masks) if masks[3] != 0 => (56, 0, 3u32),
+        Some(_) => (40, 3, 3u32),
+        None => (40, 0, 0u32),
+    };
+    let data_offset = 14 + header_size + mask_count * 4 + palette.len() * 4;
+    let data_length = stride * image.height as usize;
+    let mut buf: Vec<u8> = Vec::with_capacity(data_offset + data_length);
+    buf.extend_from_slice(b"BM");
+    buf.extend_from_slice(&to_u32(data_offset + data_length)?.to_le_bytes());
+    buf.extend_from_slice(&[0; 4]);
+    buf.extend_from_slice(&to_u32(data_offset)?.to_le_bytes());
+    buf.extend_from_slice(&(header_size as u32).to_le_bytes());
+    buf.extend_from_slice(&image.width.to_le_bytes());
+    buf.extend_from_slice(&image.height.to_le_bytes());
+    buf.extend_from_slice(&1u16.to_le_bytes());
+    buf.extend_from_slice(&options.bits_per_pixel.to_le_bytes());
+    buf.extend_from_slice(&compression.to_le_bytes());
+    buf.extend_from_slice(&to_u32(data_length)?.to_le_bytes());
+    // 72 DPI
+    buf.extend_from_slice(&2835u32.to_le_bytes());
+    buf.extend_from_slice(&2835u32.to_le_bytes());
+    buf.extend_from_slice(&to_u32(palette.len())?.to_le_bytes());
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    // the V3 header's masks, or the masks following a 40 byte header
+    if options.masks.is_some() {
+        let count = if header_size == 56 { 4 } else { mask_count };
+        for mask in &masks[..count] {
+            buf.extend_from_slice(&mask.to_le_bytes());
+        }
+    }
+    for [r, g, b] in &palette {
+        buf.extend_from_slice(&[*b, *g, *r, 0]);
+    }
+
+    // write rows, bottom-up
+    let mut indexes: HashMap<[u8; 3], u8> = HashMap::new();
+    for y in (0..image.height as usize).rev() {
+        let mut row = vec![0u8; stride];
+        for (x, [r, g, b]) in colors[y * width..(y + 1) * width]
+            .iter()
+            .copied()
+            .enumerate()
+        {
+            if bits <= 8 {
+                let index = *indexes
+                    .entry([r, g, b])
+                    .or_insert_with(|| nearest(&palette, [r, g, b]));
+                let bit = x * bits;
+                row[bit / 8] |= index << (8 - bits - bit % 8);
+                continue;
+            }
+            let alpha = image.pixels[(y * width + x) * 4 + 3];
+            let value = [r, g, b, alpha]
+                .iter()
+                .zip(masks)
+                .fold(0u32, |v, (c, mask)| v | pack(mask, *c));
+            let start = x * bits / 8;
+            row[start..start + bits / 8].copy_from_slice(&value.to_le_bytes()[..bits / 8]);
+        }
+        buf.extend_from_slice(&row);
+    }
+    Ok(buf)
+}
+
+/// The index of the palette color closest to `color`.
+fn nearest(palette: &[[u8; 3]], color: [u8; 3]) -> u8 {
+    let distance = |p: &[u8; 3]| -> u32 {
+        (0..3)
+            .map(|i| (p[i] as i32 - color[i] as i32).pow(2) as u32)
+            .sum()
+    };
+    (0..palette.len())
+        .min_by_key(|i| distance(&palette[*i]))
+        .unwrap_or(0) as u8
+}
+
+fn to_u32(n: usize) -> Result<u32, FarError> {
+    u32::try_from(n).map_err(|_| image_error("the bitmap is larger than 4 GiB"))
+}
+
+/// Check there is enough pixel data for the image before allocating it. Uncompressed data must
+/// hold every row. RLE data can end early or skip pixels, so any length is accepted.
+fn check_data_length(data: &[u8], header: &BmpHeader) -> Result<(), FarError> {
+    let enough = match header.compression {
+        Compression::Rle8 | Compression::Rle4 => true,
+        Compression::Rgb | Compression::Bitfields => {
+            let (stride, last_row) = row_lengths(header);
+            data.len() >= stride * (header.height as usize - 1) + last_row
+        }
+    };
+    if !enough {
+        return Err(image_error("truncated pixel data"));
+    }
+    Ok(())
+}
+
+/// The length of a row of uncompressed pixel data, padded to four bytes, and of the last row,
+/// which may not be padded.
+fn row_lengths(header: &BmpHeader) -> (usize, usize) {
+    let bits = header.bits_per_pixel as usize * header.width as usize;
+    (bits.div_ceil(32) * 4, bits.div_ceil(8))
+}
+
+fn decode_rows(data: &[u8], header: &BmpHeader, image: &mut RgbaImage) -> Result<(), FarError> {
+    let bits = header.bits_per_pixel as usize;
+    let (stride, _) = row_lengths(header);
+    for row in 0..header.height {
+        let src = &data[row as usize * stride..];
+        let y = image_row(header, row);
+        for x in 0..header.width {
+            let pixel = if bits <= 8 {
+                let per_byte = 8 / bits;
+                let byte = src[x as usize / per_byte];
+                let shift = 8 - bits - (x as usize % per_byte) * bits;
+                let index = (byte >> shift) & ((1 << bits) - 1) as u8;
+                palette_color(header, index)
+            } else {
+                let start = x as usize * bits / 8;
+                let value = src[start..start + bits / 8]
+                    .iter()
+                    .rev()
+                    .fold(0u32, |acc, b| (acc << 8) | *b as u32);
+                masked_color(&header.masks, value)
+            };
+            image.put_pixel(x, y, pixel);
+        }
+    }
+    Ok(())
+}
+
+/// Decode run-length encoded pixel data. Pixels skipped by a delta are left transparent.
+fn decode_rle(data: &[u8], header: &BmpHeader, image: &mut RgbaImage) -> Result<(), FarError> {
+    let rle4 = header.compression == Compression::Rle4;
+    // position counting rows from the first stored one
+    let (mut x, mut row) = (0u32, 0u32);
+    let mut put = |x: u32, row: u32, index: u8| {
+        if x < header.width && row < header.height {
+            image.put_pixel(x, image_row(header, row), palette_color(header, index));
+        }
+    };
+    let mut pos = 0;
+    // a missing end of bitmap marker is treated like one
+    while let Some(&[count, value]) = data.get(pos..pos + 2) {
+        pos += 2;
+        if count > 0 {
+            // encoded run, alternating between the two nibbles for RLE4
+            for i in 0..count {
+                let index = match (rle4, i % 2) {
+                    (false, _) => value,
+                    (true, 0) => value >> 4,
+                    (true, _) => value & 0x0F,
+                };
+                put(x, row, index);
+                x += 1;
+            }
+            continue;
+        }
+        match value {
+            // end of line
+            0 => {
+                x = 0;
+                row += 1;
+            }
+            // end of bitmap
+            1 => break,
+            // delta
+            2 => {
+                let delta = data
+                    .get(pos..pos + 2)
+                    .ok_or_else(|| image_error("truncated RLE delta"))?;
+                x += delta[0] as u32;
+                row += delta[1] as u32;
+                pos += 2;
+            }
+            // absolute run, padded to two bytes
+            n => {
+                let length = if rle4 {
+                    (n as usize).div_ceil(2)
+                } else {
+                    n as usize
+                };
+                let run = data
+                    .get(pos..pos + length)
+                    .ok_or_else(|| image_error("truncated RLE run"))?;
+                for i in 0..n as usize {
+                    let index = match (rle4, i % 2) {
+                        (false, _) => run[i],
+                        (true, 0) => run[i / 2] >> 4,
+                        (true, _) => run[i / 2] & 0x0F,
+                    };
+                    put(x, row, index);
+                    x += 1;
+                }
+                pos += length + length % 2;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The row of the image that stored row `row` goes to.
+fn image_row(header: &BmpHeader, row: u32) -> u32 {
+    if header.top_down {
+        row
+    } else {
+        header.height - 1 - row
+    }
+}
+
+/// The color of a palette index. Indexes past the end of the palette are black.
+fn palette_color(header: &BmpHeader, index: u8) -> [u8; 4] {
+    let [r, g, b] = header
+        .palette
+        .get(index as usize)
+        .copied()
+        .unwrap_or([0, 0, 0]);
+    [r, g, b, 255]
+}
+
+fn masked_color(masks: &[u32; 4], value: u32) -> [u8; 4] {
+    let alpha = if masks[3] == 0 {
+        255
+    } else {
+        channel(masks[3], value)
+    };
+    [
+        channel(masks[0], value),
+        channel(masks[1], value),
+        channel(masks[2], value),
+        alpha,
+    ]
+}
+
+/// Scale the eight bit channel `c` to the bits under `mask`.
+fn pack(mask: u32, c: u8) -> u32 {
+    if mask == 0 {
+        return 0;
+    }
+    let shift = mask.trailing_zeros();
+    let max = (mask >> shift) as u64;
+    (((c as u64 * max + 127) / 255) as u32) << shift & mask
+}
+
+/// Scale the bits of `value` under `mask` to eight bits.
+fn channel(mask: u32, value: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let shift = mask.trailing_zeros();
+    let max = (mask >> shift) as u64;
+    ((((value & mask) >> shift) as u64 * 255 + max / 2) / max) as u8
+}
+
+/// Whether the compression field of a 40 byte header says there is an alpha mask.
+fn compression_is_alpha(bytes: &[u8]) -> bool {
+    u32_at(bytes, 30).is_ok_and(|compression| compression == 6)
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Result<u16, FarError> {
+    match bytes.get(offset..offset + 2) {
+        Some(b) => Ok(u16::from_le_bytes([b[0], b[1]])),
+        None => Err(image_error("truncated header")),
+    }
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32, FarError> {
+    match bytes.get(offset..offset + 4) {
+        Some(b) => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        None => Err(image_error("truncated header")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Far;
+
+    /// A bitmap with a 40 byte header.
+    fn bmp(
+        width: i32,
+        height: i32,
+        bits: u16,
+        compression: u32,
+        palette: &[[u8; 3]],
+        data: &[u8],
+    ) -> Vec<u8> {
+        let data_offset = 54 + palette.len() as u32 * 4;
+        let mut buf: Vec<u8> = b"BM".to_vec();
+        buf.extend_from_slice(&(data_offset + data.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&[0; 4]);
+        buf.extend_from_slice(&data_offset.to_le_bytes());
+        buf.extend_from_slice(&40u32.to_le_bytes());
+        buf.extend_from_slice(&width.to_le_bytes());
+        buf.extend_from_slice(&height.to_le_bytes());
+        buf.extend_from_slice(&1u16.to_le_bytes());
+        buf.extend_from_slice(&bits.to_le_bytes());
+        buf.extend_from_slice(&compression.to_le_bytes());
+        buf.extend_from_slice(&[0; 12]);
+        buf.extend_from_slice(&(palette.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&[0; 4]);
+        for [r, g, b] in palette {
+            buf.extend_from_slice(&[*b, *g, *r, 0]);
+        }
+        buf.extend_from_slice(data);
+        buf
+    }
+
+    #[test]
+    fn test_bitfields() {
+        let far = Far::new("test.far").unwrap();
+        let bytes = far.get("test.bmp").unwrap().get_bytes().unwrap();
+        let header = read_header(&bytes).unwrap();
+        assert_eq!(header.compression, Compression::Bitfields);
+        assert_eq!(header.bits_per_pixel, 24);
+        let image = decode(&bytes).unwrap();
+        assert_eq!((image.width, image.height), (1, 1));
+        assert_eq!(image.pixels, [5, 5, 5, 255]);
+    }
+
+    #[test]
+    fn test_palettized() {
+        let palette = [[0, 0, 0], [255, 0, 255], [10, 20, 30]];
+        // bottom-up, rows padded to four bytes
+        let data = [2, 1, 0, 0, 0, 2, 0, 0];
+        let image = decode(&bmp(2, 2, 8, 0, &palette, &data)).unwrap();
+        assert_eq!(image.get_pixel(0, 0), [0, 0, 0, 255]);
+        assert_eq!(image.get_pixel(1, 0), [10, 20, 30, 255]);
+        assert_eq!(image.get_pixel(0, 1), [10, 20, 30, 255]);
+        assert_eq!(image.get_pixel(1, 1), [255, 0, 255, 0]);
+
+        let options = DecodeOptions { color_key: None };
+        let image = decode_with_options(&bmp(2, 2, 8, 0, &palette, &data), &options).unwrap();
+        assert_eq!(image.get_pixel(1, 1), [255, 0, 255, 255]);
+
+        // 4-bit, top-down
+        let image = decode(&bmp(3, -1, 4, 0, &palette, &[0x21, 0x00, 0, 0])).unwrap();
+        assert_eq!(image.get_pixel(0, 0), [10, 20, 30, 255]);
+        assert_eq!(image.get_pixel(1, 0)[3], 0);
+        assert_eq!(image.get_pixel(2, 0), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_rle() {
+        let palette = [[0, 0, 0], [1, 1, 1], [2, 2, 2]];
+        // a run of two 1s, end of line, an absolute run of 2 0 1 padded, delta to the end
+        let data = [2, 1, 0, 0, 0, 3, 2, 0, 1, 0, 0, 1];
+        let image = decode(&bmp(3, 2, 8, 1, &palette, &data)).unwrap();
+        assert_eq!(image.get_pixel(0, 1), [1, 1, 1, 255]);
+        assert_eq!(image.get_pixel(2, 1), [0, 0, 0, 0]);
+        assert_eq!(image.get_pixel(0, 0), [2, 2, 2, 255]);
+        assert_eq!(image.get_pixel(2, 0), [1, 1, 1, 255]);
+
+        let data = [3, 0x12, 0, 1];
+        let image = decode(&bmp(3, 1, 4, 2, &palette, &data)).unwrap();
+        assert_eq!(image.get_pixel(1, 0), [2, 2, 2, 255]);
+        assert_eq!(image.get_pixel(2, 0), [1, 1, 1, 255]);
+
+        // an early end of bitmap leaves the rest transparent
+        let image = decode(&bmp(64, 64, 8, 1, &palette, &[0, 1])).unwrap();
+        assert_eq!(image.pixels, vec![0; 64 * 64 * 4]);
+
+        // too little uncompressed data is rejected before allocating
+        assert!(decode(&bmp(8192, 8192, 8, 0, &palette, &[0; 16])).is_err());
+    }
+
+    #[test]
+    fn test_encode() {
+        let mut image = RgbaImage::new(3, 2);
+        image.put_pixel(0, 0, [10, 20, 30, 255]);
+        image.put_pixel(1, 0, [11, 21, 31, 255]);
+        image.put_pixel(2, 1, [200, 200, 200, 255]);
+
+        // the nearest palette colors, with transparent pixels in magenta
+        let palette = vec![[255, 0, 255], [10, 20, 30], [255, 255, 255]];
+        let header = read_header(&bmp(1, 1, 8, 0, &palette, &[0, 0, 0, 0])).unwrap();
+        let bytes = encode(&image, &EncodeOptions::like(&header).unwrap()).unwrap();
+        let header = read_header(&bytes).unwrap();
+        assert_eq!((header.bits_per_pixel, header.palette), (8, palette));
+        let decoded = decode(&bytes).unwrap();
+        assert_eq!(decoded.get_pixel(1, 0), [10, 20, 30, 255]);
+        assert_eq!(decoded.get_pixel(0, 1), [255, 0, 255, 0]);
+        assert_eq!(decoded.get_pixel(2, 1), [255, 255, 255, 255]);
+
+        let bytes = encode(&image, &EncodeOptions::default()).unwrap();
+        assert_eq!(decode(&bytes).unwrap(), {
+            let mut expected = image.clone();
+            expected.put_pixel(2, 0, [255, 0, 255, 0]);
+            expected.put_pixel(0, 1, [255, 0, 255, 0]);
+            expected.put_pixel(1, 1, [255, 0, 255, 0]);
+            expected
+        });
+
+        let options = EncodeOptions {
+            bits_per_pixel: 1,
+            ..EncodeOptions::default()
+        };
+        assert!(encode(&image, &options).is_err());
+
+        // transparent pixels need the color key in the palette
+        let options = EncodeOptions {
+            bits_per_pixel: 8,
+            palette: Some(vec![[10, 20, 30], [255, 255, 255]]),
+            ..EncodeOptions::default()
+        };
+        assert!(encode(&image, &options).is_err());
+
+        let rle = read_header(&bmp(1, 1, 8, 1, &[[0, 0, 0]], &[0, 1])).unwrap();
+        assert!(EncodeOptions::like(&rle).is_err());
+    }
+
+    #[test]
+    fn test_encode_masks() {
+        let mut image = RgbaImage::new(2, 1);
+        image.put_pixel(0, 0, [255, 0, 0, 255]);
+        image.put_pixel(1, 0, [0, 255, 0, 128]);
+
+        // 5-6-5, kept by like
+        let options = EncodeOptions {
+            bits_per_pixel: 16,
+            masks: Some([0xF800, 0x07E0, 0x001F, 0]),
+            color_key: None,
+            ..EncodeOptions::default()
+        };
+        let bytes = encode(&image, &options).unwrap();
+        let header = read_header(&bytes).unwrap();
+        assert_eq!(header.compression, Compression::Bitfields);
+        assert_eq!(EncodeOptions::like(&header).unwrap().masks, options.masks);
+        assert_eq!(&bytes[66..70], &[0x00, 0xF8, 0xE0, 0x07]);
+
+        // alpha mask in a V3 header
+        let options = EncodeOptions {
+            bits_per_pixel: 32,
+            masks: Some([0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0xFF00_0000]),
+            ..EncodeOptions::default()
+        };
+        let bytes = encode(&image, &options).unwrap();
+        assert_eq!(read_header(&bytes).unwrap().masks, options.masks.unwrap());
+        assert_eq!(decode(&bytes).unwrap(), image);
+
+        let options = EncodeOptions {
+            bits_per_pixel: 16,
+            masks: Some([0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0]),
+            ..EncodeOptions::default()
+        };
+        assert!(encode(&image, &options).is_err());
+    }
+
+    #[test]
+    fn test_high_color() {
+        // 5-5-5 red, then 8-8-8 blue
+        let image = decode(&bmp(1, 1, 16, 0, &[], &[0x00, 0x7C, 0, 0])).unwrap();
+        assert_eq!(image.pixels, [255, 0, 0, 255]);
+        let image = decode(&bmp(1, 1, 24, 0, &[], &[0xFF, 0, 0, 0])).unwrap();
+        assert_eq!(image.pixels, [0, 0, 255, 255]);
+
+        assert!(decode(&bmp(2, 2, 24, 0, &[], &[0; 8])).is_err());
+        assert!(decode(b"BM").is_err());
+    }
+}
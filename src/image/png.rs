@@ -0,0 +1,111 @@
+//! PNG input and output, for editing the images of a far file in modern tools.
+
+use super::bmp::{self, EncodeOptions};
+use super::{check_dimensions, image_error, RgbaImage};
+use crate::{Far, FarError, ManifestEntry};
+use std::io::{Read, Write};
+
+impl RgbaImage {
+    /// Write the image as an 8-bit RGBA PNG.
+    pub fn write_png<W: Write>(&self, w: W) -> Result<(), FarError> {
+        let mut encoder = png::Encoder::new(w, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Read a PNG. Every color type and bit depth is converted to 8-bit RGBA.
+    pub fn read_png<R: Read>(r: R) -> Result<RgbaImage, FarError> {
+        let mut decoder = png::Decoder::new(r);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        let (width, height) = (reader.info().width, reader.info().height);
+        check_dimensions(width as u64, height as u64)?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+        buf.truncate(info.buffer_size());
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buf,
+            png::ColorType::Rgb => buf
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => buf
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            png::ColorType::Grayscale => buf.iter().flat_map(|v| [*v, *v, *v, 255]).collect(),
+            // expanded to RGB(A) by the decoder
+            png::ColorType::Indexed => return Err(image_error("unexpanded indexed PNG")),
+        };
+        Ok(RgbaImage {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+/// Convert a PNG to a bitmap the game accepts, with fully transparent pixels in magenta.
+/// `original` is the bitmap the PNG replaces: the bitmap is written with its depth, palette and
+/// color masks, and converting fails if it can't be. Without one a 24-bit bitmap is written.
+pub fn png_to_bmp(png: &[u8], original: Option<&[u8]>) -> Result<Vec<u8>, FarError> {
+    let image = RgbaImage::read_png(png)?;
+    let options = match original {
+        Some(original) => EncodeOptions::like(&bmp::read_header(original)?)?,
+        None => EncodeOptions::default(),
+    };
+    bmp::encode(&image, &options)
+}
+
+impl Far {
+    /// Decode an image in the far file and write it as a PNG. Bitmaps have magenta made
+    /// transparent, so the PNG has real alpha. Fails if the file isn't a bitmap or Targa image.
+    /// See [`super::decode`].
+    pub fn export_png<W: Write>(&self, entry: &ManifestEntry, w: W) -> Result<(), FarError> {
+        let image = super::decode(&entry.file_name, &entry.get_bytes()?)?;
+        image.write_png(w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_png() {
+        let far = Far::new("test.far").unwrap();
+        let mut buf: Vec<u8> = vec![];
+        far.export_png(far.get("test.bmp").unwrap(), &mut buf)
+            .unwrap();
+
+        let decoder = png::Decoder::new(&buf[..]);
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (1, 1));
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        assert_eq!(pixels, [5, 5, 5, 255]);
+    }
+
+    #[test]
+    fn test_png_to_bmp() {
+        let mut image = RgbaImage::new(2, 1);
+        image.put_pixel(0, 0, [5, 5, 5, 255]);
+        let mut png: Vec<u8> = vec![];
+        image.write_png(&mut png).unwrap();
+        assert_eq!(RgbaImage::read_png(&png[..]).unwrap(), image);
+
+        let far = Far::new("test.far").unwrap();
+        let original = far.get("test.bmp").unwrap().get_bytes().unwrap();
+        let bytes = png_to_bmp(&png, Some(&original)).unwrap();
+        assert_eq!(bmp::read_header(&bytes).unwrap().bits_per_pixel, 24);
+        let decoded = bmp::decode(&bytes).unwrap();
+        assert_eq!(decoded.get_pixel(0, 0), [5, 5, 5, 255]);
+        assert_eq!(decoded.get_pixel(1, 0), [255, 0, 255, 0]);
+        assert!(png_to_bmp(&png, Some(b"not a bitmap")).is_err());
+    }
+}
@@ -0,0 +1,286 @@
+//! Targa images, used by some of UIGraphics.far and by skin textures.
+//!
+//! Color-mapped, true-color and grayscale images are decoded, uncompressed or run-length
+//! encoded, with 8, 15, 16, 24 or 32 bits per pixel and any origin.
+
+use super::{check_dimensions, image_error, RgbaImage};
+use crate::FarError;
+
+/// What the pixels of a Targa image hold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageType {
+    /// Indexes into a color map.
+    ColorMapped,
+    /// Colors.
+    TrueColor,
+    /// Shades of gray.
+    Grayscale,
+}
+
+/// The fields of a Targa header that describe its pixels.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TgaHeader {
+    /// The width in pixels.
+    pub width: u32,
+    /// The height in pixels.
+    pub height: u32,
+    /// What the pixels hold.
+    pub image_type: ImageType,
+    /// Whether the pixels are run-length encoded.
+    pub rle: bool,
+    /// The number of bits per pixel: 8, 15, 16, 24 or 32.
+    pub pixel_depth: u8,
+    /// The number of alpha bits in each pixel, or in each color map entry for color-mapped
+    /// images.
+    pub alpha_bits: u8,
+    /// Whether the first row of pixel data is the top row. Most images are stored bottom-up.
+    pub top_down: bool,
+    /// Whether each row of pixel data runs from right to left.
+    pub right_to_left: bool,
+}
+
+/// Read the header of the Targa image in `bytes`.
+pub fn read_header(bytes: &[u8]) -> Result<TgaHeader, FarError> {
+    let header = bytes
+        .get(..18)
+        .ok_or_else(|| image_error("truncated header"))?;
+    let (image_type, rle) = match header[2] {
+        1 => (ImageType::ColorMapped, false),
+        2 => (ImageType::TrueColor, false),
+        3 => (ImageType::Grayscale, false),
+        9 => (ImageType::ColorMapped, true),
+        10 => (ImageType::TrueColor, true),
+        11 => (ImageType::Grayscale, true),
+        t => return Err(image_error(&format!("unsupported image type {t}"))),
+    };
+    let pixel_depth = header[16];
+    let supported = match image_type {
+        ImageType::ColorMapped => matches!(pixel_depth, 8 | 16),
+        ImageType::TrueColor => matches!(pixel_depth, 15 | 16 | 24 | 32),
+        ImageType::Grayscale => matches!(pixel_depth, 8 | 16),
+    };
+    if !supported {
+        return Err(image_error(&format!(
+            "unsupported pixel depth {pixel_depth} for {image_type:?}"
+        )));
+    }
+    let width = u16::from_le_bytes([header[12], header[13]]) as u32;
+    let height = u16::from_le_bytes([header[14], header[15]]) as u32;
+    check_dimensions(width as u64, height as u64)?;
+    Ok(TgaHeader {
+        width,
+        height,
+        image_type,
+        rle,
+        pixel_depth,
+        alpha_bits: header[17] & 0x0F,
+        top_down: header[17] & 0x20 != 0,
+        right_to_left: header[17] & 0x10 != 0,
+    })
+}
+
+/// Decode the Targa image in `bytes`.
+pub fn decode(bytes: &[u8]) -> Result<RgbaImage, FarError> {
+    let header = read_header(bytes)?;
+
+    // read color map, after the image ID
+    let id_length = bytes[0] as usize;
+    let color_map_first = u16::from_le_bytes([bytes[3], bytes[4]]) as usize;
+    let color_map_length = u16::from_le_bytes([bytes[5], bytes[6]]) as usize;
+    let color_map_depth = bytes[7];
+    let mut pos = 18 + id_length;
+    let mut color_map: Vec<[u8; 4]> = vec![];
+    if bytes[1] == 1 {
+        let entry_length = (color_map_depth as usize).div_ceil(8);
+        if !matches!(color_map_depth, 15 | 16 | 24 | 32) {
+            return Err(image_error(&format!(
+                "unsupported color map depth {color_map_depth}"
+            )));
+        }
+        let entries = bytes
+            .get(pos..pos + color_map_length * entry_length)
+            .ok_or_else(|| image_error("truncated color map"))?;
+        color_map = entries
+            .chunks_exact(entry_length)
+            .map(|entry| color(entry, color_map_depth, header.alpha_bits))
+            .collect();
+        pos += entries.len();
+    } else if header.image_type == ImageType::ColorMapped {
+        return Err(image_error("color-mapped image without a color map"));
+    }
+
+    // check there is enough pixel data before allocating, a packet draws at most 128 pixels
+    let count = header.width as usize * header.height as usize;
+    let pixel_length = (header.pixel_depth as usize).div_ceil(8);
+    let data = &bytes[pos.min(bytes.len())..];
+    let enough = if header.rle {
+        count <= data.len() / (1 + pixel_length) * 128
+    } else {
+        data.len() >= count * pixel_length
+    };
+    if !enough {
+        return Err(image_error("truncated pixel data"));
+    }
+
+    // read pixels in the order they are stored
+    let mut image = RgbaImage::new(header.width, header.height);
+    let mut put = |i: usize, pixel: &[u8]| {
+        let (mut x, mut y) = (i as u32 % header.width, i as u32 / header.width);
+        if header.right_to_left {
+            x = header.width - 1 - x;
+        }
+        if !header.top_down {
+            y = header.height - 1 - y;
+        }
+        let rgba = match header.image_type {
+            ImageType::ColorMapped => {
+                let index = match pixel {
+                    [index] => *index as usize,
+                    _ => u16::from_le_bytes([pixel[0], pixel[1]]) as usize,
+                };
+                // indexes outside the color map are black
+                index
+                    .checked_sub(color_map_first)
+                    .and_then(|i| color_map.get(i))
+                    .copied()
+                    .unwrap_or([0, 0, 0, 255])
+            }
+            ImageType::TrueColor => color(pixel, header.pixel_depth, header.alpha_bits),
+            ImageType::Grayscale => match pixel {
+                [v] => [*v, *v, *v, 255],
+                _ if header.alpha_bits > 0 => [pixel[0], pixel[0], pixel[0], pixel[1]],
+                _ => [pixel[0], pixel[0], pixel[0], 255],
+            },
+        };
+        image.put_pixel(x, y, rgba);
+    };
+    if header.rle {
+        read_rle(data, pixel_length, count, &mut put)?;
+    } else {
+        for (i, pixel) in data.chunks_exact(pixel_length).take(count).enumerate() {
+            put(i, pixel);
+        }
+    }
+    Ok(image)
+}
+
+/// Expand run-length encoded pixel data into `count` pixels, passing each to `put` with its
+/// index. Packets may run across rows.
+fn read_rle(
+    data: &[u8],
+    pixel_length: usize,
+    count: usize,
+    put: &mut impl FnMut(usize, &[u8]),
+) -> Result<(), FarError> {
+    let (mut i, mut pos) = (0, 0);
+    while i < count {
+        let packet = *data
+            .get(pos)
+            .ok_or_else(|| image_error("truncated RLE packet"))?;
+        pos += 1;
+        let length = (packet & 0x7F) as usize + 1;
+        let repeated = packet & 0x80 != 0;
+        let values = if repeated { 1 } else { length };
+        let bytes = data
+            .get(pos..pos + values * pixel_length)
+            .ok_or_else(|| image_error("truncated RLE packet"))?;
+        pos += bytes.len();
+        for j in 0..length.min(count - i) {
+            let value = if repeated { 0 } else { j };
+            put(i, &bytes[value * pixel_length..(value + 1) * pixel_length]);
+            i += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Decode a true-color pixel or color map entry, stored as little-endian BGR(A).
+fn color(pixel: &[u8], depth: u8, alpha_bits: u8) -> [u8; 4] {
+    match depth {
+        15 | 16 => {
+            let v = u16::from_le_bytes([pixel[0], pixel[1]]);
+            let scale = |c: u16| ((c & 0x1F) * 255 / 31) as u8;
+            let alpha = if depth == 16 && alpha_bits > 0 && v & 0x8000 == 0 {
+                0
+            } else {
+                255
+            };
+            [scale(v >> 10), scale(v >> 5), scale(v), alpha]
+        }
+        24 => [pixel[2], pixel[1], pixel[0], 255],
+        _ => {
+            let alpha = if alpha_bits > 0 { pixel[3] } else { 255 };
+            [pixel[2], pixel[1], pixel[0], alpha]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tga(image_type: u8, width: u16, height: u16, depth: u8, descriptor: u8) -> Vec<u8> {
+        let mut buf: Vec<u8> = vec![0, 0, image_type, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        buf.extend_from_slice(&width.to_le_bytes());
+        buf.extend_from_slice(&height.to_le_bytes());
+        buf.extend_from_slice(&[depth, descriptor]);
+        buf
+    }
+
+    #[test]
+    fn test_true_color() {
+        // bottom-up, so the first pixel stored is the bottom left
+        let mut bytes = tga(2, 2, 1, 24, 0);
+        bytes.extend_from_slice(&[0xFF, 0, 0, 0, 0xFF, 0]);
+        let image = decode(&bytes).unwrap();
+        assert_eq!(image.pixels, [0, 0, 255, 255, 0, 255, 0, 255]);
+
+        let mut bytes = tga(2, 1, 2, 32, 0x28);
+        bytes.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 0]);
+        let image = decode(&bytes).unwrap();
+        assert_eq!(image.get_pixel(0, 0), [3, 2, 1, 4]);
+        assert_eq!(image.get_pixel(0, 1), [7, 6, 5, 0]);
+
+        // 16-bit with one alpha bit, right to left
+        let mut bytes = tga(2, 2, 1, 16, 0x11);
+        bytes.extend_from_slice(&[0x00, 0xFC, 0x1F, 0x00]);
+        let image = decode(&bytes).unwrap();
+        assert_eq!(image.get_pixel(0, 0), [0, 0, 255, 0]);
+        assert_eq!(image.get_pixel(1, 0), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_rle() {
+        // a run of three red pixels and a raw packet of one gray pixel, across two rows
+        let mut bytes = tga(10, 2, 2, 24, 0x20);
+        bytes.extend_from_slice(&[0x82, 0, 0, 0xFF, 0x00, 9, 9, 9]);
+        let image = decode(&bytes).unwrap();
+        assert_eq!(image.get_pixel(1, 0), [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(0, 1), [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(1, 1), [9, 9, 9, 255]);
+
+        bytes.truncate(bytes.len() - 1);
+        assert!(decode(&bytes).is_err());
+
+        // a huge image in a tiny file is rejected before allocating
+        let mut bytes = tga(10, 0xFFFF, 0x400, 24, 0);
+        bytes.extend_from_slice(&[0xFF, 0, 0, 0]);
+        assert!(decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_color_mapped() {
+        let mut bytes = tga(1, 2, 1, 8, 0x20);
+        // two 24-bit color map entries starting at index 1
+        bytes[1] = 1;
+        bytes[3..8].copy_from_slice(&[1, 0, 2, 0, 24]);
+        bytes.extend_from_slice(&[0, 0, 0xFF, 0xFF, 0, 0]);
+        bytes.extend_from_slice(&[2, 1]);
+        let image = decode(&bytes).unwrap();
+        assert_eq!(image.pixels, [0, 0, 255, 255, 255, 0, 0, 255]);
+
+        let mut gray = tga(11, 1, 1, 8, 0);
+        gray.extend_from_slice(&[0x80, 7]);
+        assert_eq!(decode(&gray).unwrap().pixels, [7, 7, 7, 255]);
+    }
+}
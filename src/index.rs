@@ -0,0 +1,87 @@
+use crate::{Far, ManifestEntry};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Normalized file names to their index in the manifest entries, built on first lookup. The
+/// index is derived from the manifest, so it is left out of comparisons and debug output.
+#[derive(Clone, Default)]
+pub(crate) struct NameIndex(OnceLock<HashMap<String, usize>>);
+
+impl fmt::Debug for NameIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("NameIndex")
+    }
+}
+
+impl PartialEq for NameIndex {
+    fn eq(&self, _: &NameIndex) -> bool {
+        true
+    }
+}
+
+/// Normalize a file name the way the game compares them: case-insensitively, with `\` and `/`
+/// treated as the same directory separator.
+pub fn normalize_name(name: &str) -> String {
+    name.replace('\\', "/").to_lowercase()
+}
+
+impl Far {
+    /// Find the manifest entry for `name`, using the game's matching rules (see
+    /// [`normalize_name`]). If the manifest has the same name more than once the first entry
+    /// wins.
+    ///
+    /// The lookup index is built the first time this is called. Call [`Far::reindex`] after
+    /// changing `manifest.manifest_entries`.
+    pub fn get(&self, name: &str) -> Option<&ManifestEntry> {
+        let index = self
+            .index
+            .0
+            .get_or_init(|| build_index(&self.manifest.manifest_entries));
+        index
+            .get(&normalize_name(name))
+            .and_then(|i| self.manifest.manifest_entries.get(*i))
+    }
+
+    /// Whether the far file has a file called `name`. See [`Far::get`].
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Throw away the lookup index so it is rebuilt from the current manifest entries.
+    pub fn reindex(&mut self) {
+        self.index.0.take();
+    }
+}
+
+fn build_index(entries: &[ManifestEntry]) -> HashMap<String, usize> {
+    let mut index: HashMap<String, usize> = HashMap::with_capacity(entries.len());
+    for (i, me) in entries.iter().enumerate() {
+        index.entry(normalize_name(&me.file_name)).or_insert(i);
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::writer::test_far;
+
+    #[test]
+    fn test_get() {
+        let mut far = test_far(&[
+            ("UIGraphics\\Buttons\\Btn_OK.bmp", b"ok"),
+            ("uigraphics/buttons/btn_ok.bmp", b"shadowed"),
+            ("test.bmp", b"test"),
+        ]);
+
+        let me = far.get("UIGraphics/Buttons/Btn_ok.bmp").unwrap();
+        assert_eq!(me.get_bytes().unwrap(), b"ok");
+        assert!(far.contains("TEST.BMP"));
+        assert!(!far.contains("missing.bmp"));
+
+        far.manifest.manifest_entries.remove(0);
+        far.reindex();
+        let me = far.get("UIGraphics/Buttons/Btn_ok.bmp").unwrap();
+        assert_eq!(me.get_bytes().unwrap(), b"shadowed");
+    }
+}
@@ -0,0 +1,25 @@
+use crate::{Far, FarError};
+
+impl Far {
+    /// The header and manifest as pretty printed JSON, for diffing and cataloguing archives.
+    pub fn manifest_json(&self) -> Result<String, FarError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_json() {
+        let far = Far::new("test.far").unwrap();
+        let json = far.manifest_json().unwrap();
+        assert!(json.contains("\"file_name\": \"test.bmp\""));
+        assert!(!json.contains("source"));
+
+        let parsed: Far = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, far);
+        assert!(parsed.manifest.manifest_entries[0].get_bytes().is_err());
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{Far, FarError, ManifestEntry};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::io::SeekFrom::End;
+use std::io::{Read, Seek};
+
+/// The bytes read from the start of a file to recognize it.
+const HEAD_LENGTH: usize = 64;
+/// The Targa 2.0 footer, at the end of the file.
+const TGA_FOOTER: &[u8] = b"TRUEVISION-XFILE.\0";
+
+/// The format of a file in a far file, recognized by [`ManifestEntry::kind`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FileKind {
+    /// A Windows bitmap, used for most of UIGraphics.far.
+    Bmp,
+    /// A Targa image.
+    Tga,
+    /// An Interchange File Format file holding objects, strings and sprites.
+    Iff,
+    /// A far file inside the far file.
+    Far,
+    /// A RIFF WAVE sound.
+    Wav,
+    /// A Maxis XA compressed sound.
+    Xa,
+    /// A Maxis UTalk compressed sound.
+    Utk,
+    /// A sprite.
+    Spr,
+    /// A text skin mesh.
+    Skn,
+    /// A text character description, listing skeletons, skins and suits.
+    Cmx,
+    /// A binary character description, the compiled form of CMX.
+    Bcf,
+    /// A binary skin mesh, the compiled form of SKN.
+    Bmf,
+    /// Compressed float points, the keyframes of an animation.
+    Cfp,
+    /// A HIT sound event program.
+    Hit,
+    /// A text list of HIT sound events.
+    Evt,
+    /// Anything else.
+    Unknown,
+}
+
+impl FileKind {
+    /// Recognize a file from its name and contents. Magic bytes win over the extension, so a
+    /// misnamed file is still recognized. Formats without magic bytes are recognized by their
+    /// extension, and Targa images also need a header that makes sense.
+    ///
+    /// Only the first 64 and the last 26 bytes of `bytes` are looked at.
+    pub fn from_bytes(name: &str, bytes: &[u8]) -> FileKind {
+        let head = &bytes[..bytes.len().min(HEAD_LENGTH)];
+        let footer = &bytes[bytes.len().saturating_sub(26)..];
+        classify(name, head, footer)
+    }
+
+    /// Whether the file is an image this crate knows about.
+    pub fn is_image(self) -> bool {
+        matches!(self, FileKind::Bmp | FileKind::Tga)
+    }
+}
+
+fn classify(name: &str, head: &[u8], footer: &[u8]) -> FileKind {
+    // magic bytes
+    if head.starts_with(b"FAR!byAZ") {
+        return FileKind::Far;
+    }
+    if head.starts_with(b"IFF FILE ") {
+        return FileKind::Iff;
+    }
+    if head.starts_with(b"RIFF") && head.get(8..12) == Some(b"WAVE") {
+        return FileKind::Wav;
+    }
+    if head.starts_with(b"XAI\0") || head.starts_with(b"XAJ\0") {
+        return FileKind::Xa;
+    }
+    if head.starts_with(b"UTM0") {
+        return FileKind::Utk;
+    }
+    if head.starts_with(b"HIT!") {
+        return FileKind::Hit;
+    }
+    if head.starts_with(b"BM") && head.len() >= 14 {
+        return FileKind::Bmp;
+    }
+    if footer.len() == 26 && footer.ends_with(TGA_FOOTER) {
+        return FileKind::Tga;
+    }
+
+    // extensions
+    let extension = name
+        .rsplit(['/', '\\'])
+        .next()
+        .and_then(|file| file.rsplit_once('.'))
+        .map(|(_, extension)| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("tga") if is_tga_header(head) => FileKind::Tga,
+        Some("spr") => FileKind::Spr,
+        Some("skn") => FileKind::Skn,
+        Some("cmx") => FileKind::Cmx,
+        Some("bcf") => FileKind::Bcf,
+        Some("bmf") => FileKind::Bmf,
+        Some("cfp") => FileKind::Cfp,
+        Some("evt") => FileKind::Evt,
+        _ => FileKind::Unknown,
+    }
+}
+
+/// Whether `head` starts with a Targa header with a known image type and pixel depth.
+fn is_tga_header(head: &[u8]) -> bool {
+    if head.len() < 18 {
+        return false;
+    }
+    let width = u16::from_le_bytes([head[12], head[13]]);
+    let height = u16::from_le_bytes([head[14], head[15]]);
+    head[1] <= 1
+        && matches!(head[2], 1 | 2 | 3 | 9 | 10 | 11)
+        && matches!(head[16], 8 | 15 | 16 | 24 | 32)
+        && width > 0
+        && height > 0
+}
+
+impl ManifestEntry {
+    /// Recognize the format of the file by its magic bytes and file name. See
+    /// [`FileKind::from_bytes`]. Only the start and end of the file are read.
+    pub fn kind(&self) -> Result<FileKind, FarError> {
+        let mut reader = self.open()?;
+        let mut head: Vec<u8> = vec![];
+        (&mut reader)
+            .take(HEAD_LENGTH as u64)
+            .read_to_end(&mut head)?;
+        let mut footer: Vec<u8> = vec![];
+        if reader.len() >= 26 {
+            reader.seek(End(-26))?;
+            reader.read_to_end(&mut footer)?;
+        }
+        Ok(classify(&self.file_name, &head, &footer))
+    }
+}
+
+impl Far {
+    /// Recognize the format of a file in the far file. See [`ManifestEntry::kind`].
+    pub fn sniff(&self, entry: &ManifestEntry) -> Result<FileKind, FarError> {
+        entry.kind()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::test_far;
+
+    #[test]
+    fn test_kind() {
+        let far = Far::new("test.far").unwrap();
+        let me = &far.manifest.manifest_entries[0];
+        assert_eq!(far.sniff(me).unwrap(), FileKind::Bmp);
+
+        let mut tga = vec![0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 1, 0, 24, 0];
+        tga.extend_from_slice(&[0, 0, 0]);
+        let bmp = far.get("test.bmp").unwrap().get_bytes().unwrap();
+        let far = test_far(&[
+            ("misnamed.tga", &bmp),
+            ("Dialogs/frame.TGA", &tga),
+            ("frame.dat", &tga),
+            ("Sounds/click.xa", b"XAI\0\x10\0\0\0"),
+            ("adult.cmx", b"// character\nversion 300\n"),
+            ("notes.txt", b"text"),
+        ]);
+        let kinds: Vec<FileKind> = far
+            .manifest
+            .manifest_entries
+            .iter()
+            .map(|me| me.kind().unwrap())
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                FileKind::Bmp,
+                FileKind::Tga,
+                FileKind::Unknown,
+                FileKind::Xa,
+                FileKind::Cmx,
+                FileKind::Unknown
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        assert_eq!(FileKind::from_bytes("a.bmp", b"FAR!byAZ"), FileKind::Far);
+        assert_eq!(
+            FileKind::from_bytes("a", b"IFF FILE 2.5:TYPE FOLLOWED BY SIZE\0"),
+            FileKind::Iff
+        );
+        assert_eq!(
+            FileKind::from_bytes("a", b"RIFF\x24\0\0\0WAVEfmt "),
+            FileKind::Wav
+        );
+        let mut tga = vec![0; 18];
+        tga.extend_from_slice(&[0; 8]);
+        tga.extend_from_slice(TGA_FOOTER);
+        assert_eq!(FileKind::from_bytes("a", &tga), FileKind::Tga);
+        assert_eq!(FileKind::from_bytes("a.tga", b"short"), FileKind::Unknown);
+        assert!(FileKind::Tga.is_image());
+    }
+}
@@ -1,33 +1,127 @@
 #![crate_name = "sims_far"]
 
-use std::convert::Infallible;
 use std::fs::File;
 use std::io;
-use std::io::SeekFrom::Start;
-use std::io::{Read, Seek};
-use std::str::{from_utf8, Utf8Error};
+use std::io::SeekFrom::{End, Start};
+use std::io::{BufReader, Cursor, Read, Seek};
+use std::str::Utf8Error;
 use thiserror::Error;
 
+mod cp1252;
+mod diff;
+mod editor;
+mod entry_reader;
+mod glob;
+#[cfg(any(feature = "crc32", feature = "sha256"))]
+mod hash;
+pub mod image;
+mod index;
+#[cfg(feature = "serde")]
+mod json;
+mod kind;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod overlay;
+mod refpack;
+mod source;
+mod tree;
+mod verify;
+mod writer;
+
+use index::NameIndex;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use source::{ReadSeek, Source};
+
+pub use diff::{diff, FarDiff, Modified, Renamed};
+pub use editor::FarEditor;
+pub use entry_reader::EntryReader;
+pub use glob::Pattern;
+#[cfg(any(feature = "crc32", feature = "sha256"))]
+pub use hash::{Digest, HashAlgorithm, HashList, HashMismatch};
+pub use index::normalize_name;
+pub use kind::FileKind;
+#[cfg(feature = "mmap")]
+pub use mmap::MmapFar;
+pub use overlay::{FarStack, Resolved, Shadowed};
+pub use tree::{DirEntry, DirTree};
+pub use verify::{Problem, VerifyReport};
+pub use writer::FarWriter;
+
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum FarError {
     #[error("File error: {0}")]
     FileError(#[from] io::Error),
     #[error("utf8 error: {0}")]
     Utf8Error(#[from] Utf8Error),
-    #[error("infallible error: {0}")]
-    InfallibleError(#[from] Infallible),
+    #[error("refpack error: {0}")]
+    RefPackError(String),
+    #[error("pattern error: {0}")]
+    PatternError(String),
+    #[error("image error: {0}")]
+    ImageError(String),
+    #[cfg(any(feature = "crc32", feature = "sha256"))]
+    #[error("hash list error: {0}")]
+    HashListError(String),
+    #[error("{name:?} is not in the far file")]
+    EntryNotFound { name: String },
+    #[error("{name:?} is already in the far file")]
+    EntryExists { name: String },
+    #[cfg(feature = "serde")]
+    #[error("json error: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[cfg(feature = "image")]
+    #[error("png error: {0}")]
+    PngEncodingError(#[from] png::EncodingError),
+    #[cfg(feature = "image")]
+    #[error("png error: {0}")]
+    PngDecodingError(#[from] png::DecodingError),
+    #[error("the header is truncated, the file is only {file_length} bytes long")]
+    TruncatedHeader { file_length: u64 },
+    #[error("bad signature {signature:?} at offset {offset}")]
+    BadSignature { signature: Vec<u8>, offset: u64 },
+    #[error("unsupported version {version} at offset {offset}")]
+    UnsupportedVersion { version: u32, offset: u64 },
+    #[error(
+        "the manifest extends past the end of the file ({file_length} bytes) at offset {offset}"
+    )]
+    ManifestOutOfBounds { offset: u64, file_length: u64 },
+    #[error(
+        "{name:?} at offset {offset} with length {len} extends past the end of the file \
+         ({file_length} bytes)"
+    )]
+    EntryOutOfBounds {
+        name: String,
+        offset: u64,
+        len: u64,
+        file_length: u64,
+    },
+    #[error(
+        "{name:?} has different file lengths {file_length1} and {file_length2} in the manifest \
+         entry at offset {offset}"
+    )]
+    LengthMismatch {
+        name: String,
+        offset: u64,
+        file_length1: u32,
+        file_length2: u32,
+    },
 }
 
 /// The FAR format (.far files) are used to bundle (archive) multiple files together. All numeric
 /// values in the header and manifest are stored in little-endian order(least significant byte
 /// first).
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Far {
     /// The signature is an eight-byte string, consisting literally of "FAR!byAZ" (without the
     /// quotes).
     pub signature: String,
-    /// The version is always one.
+    /// The version is one for The Sims and three for The Sims Online.
     pub version: u32,
+    /// The layout of the manifest entries, which differs between version one archives.
+    pub variant: FarVariant,
     /// The manifest offset is the byte offset from the beginning of the file to the manifest.
     /// The contents of the archived files are simply concatenated together without any other
     /// structure or padding.Caveat: all of the files observed have been a multiple of four in
@@ -38,19 +132,120 @@ pub struct Far {
     /// each file. In all of the examples examined the order of the entries matches the order of
     /// the archived files, but whether this is a firm requirement or not is unknown.
     pub manifest: Manifest,
+    /// Normalized file names to their index in the manifest entries, built on first lookup.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    index: NameIndex,
+    /// The far file the manifest was parsed from.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    source: Source,
+}
+
+/// The layout of the manifest entries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FarVariant {
+    /// Version one, with the filename length stored in 16 bits.
+    V1a,
+    /// Version one, with the filename length stored in 32 bits. This is the variant used by
+    /// UIGraphics.far.
+    V1b,
+    /// Version three, used by The Sims Online.
+    V3,
+}
+
+/// Options that control how a far file is parsed.
+#[derive(Clone, Debug)]
+pub struct ParseOptions {
+    /// The variant of version one archives. When `None` the variant is detected by checking which
+    /// layout fits the manifest. Version three archives ignore this option.
+    pub variant: Option<FarVariant>,
+    /// Stop at the first problem. When false, problems are returned as diagnostics and manifest
+    /// entries that can't be read are skipped. Defaults to true.
+    pub strict: bool,
+    /// When not strict and the manifest offset doesn't point at a manifest, search the far file
+    /// for one. Defaults to false.
+    pub recover_manifest: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions {
+            variant: None,
+            strict: true,
+            recover_manifest: false,
+        }
+    }
+}
+
+/// A problem found while parsing a far file in lenient mode.
+#[derive(Debug)]
+pub struct Diagnostic {
+    /// The index of the manifest entry the problem was found in, or `None` for the header and
+    /// the manifest as a whole.
+    pub entry: Option<u32>,
+    /// The problem.
+    pub error: FarError,
 }
 
 impl Far {
     /// Create a new instance of Far and parse it
     pub fn new(path: &str) -> Result<Far, FarError> {
-        return parse_far(path);
+        Far::new_with_options(path, &ParseOptions::default())
+    }
+
+    /// Create a new instance of Far and parse it with the given options.
+    pub fn new_with_options(path: &str, options: &ParseOptions) -> Result<Far, FarError> {
+        Far::from_reader_with_options(BufReader::new(File::open(path)?), options)
+    }
+
+    /// Parse a possibly damaged far file, skipping the manifest entries that can't be read and
+    /// searching for the manifest if the header doesn't point at one. Returns the far file and
+    /// every problem that was found.
+    pub fn open_lenient(path: &str) -> Result<(Far, Vec<Diagnostic>), FarError> {
+        let options = ParseOptions {
+            strict: false,
+            recover_manifest: true,
+            ..ParseOptions::default()
+        };
+        Far::from_reader_with_diagnostics(BufReader::new(File::open(path)?), &options)
+    }
+
+    /// Parse a far file from any seekable reader. The reader is kept open and shared by the
+    /// manifest entries so their contents can be read with [`ManifestEntry::get_bytes`].
+    pub fn from_reader<R: Read + Seek + Send + 'static>(reader: R) -> Result<Far, FarError> {
+        Far::from_reader_with_options(reader, &ParseOptions::default())
+    }
+
+    /// Parse a far file from any seekable reader with the given options. Diagnostics from lenient
+    /// parsing are discarded, use [`Far::from_reader_with_diagnostics`] to keep them.
+    pub fn from_reader_with_options<R: Read + Seek + Send + 'static>(
+        reader: R,
+        options: &ParseOptions,
+    ) -> Result<Far, FarError> {
+        Ok(parse_far(Source::new(reader), options)?.0)
+    }
+
+    /// Parse a far file from any seekable reader with the given options, returning the problems
+    /// found along the way. Strict parsing always returns no diagnostics.
+    pub fn from_reader_with_diagnostics<R: Read + Seek + Send + 'static>(
+        reader: R,
+        options: &ParseOptions,
+    ) -> Result<(Far, Vec<Diagnostic>), FarError> {
+        parse_far(Source::new(reader), options)
+    }
+
+    /// Parse a far file held in memory. The bytes are copied so the returned Far does not borrow
+    /// from `bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Far, FarError> {
+        Far::from_reader(Cursor::new(bytes.to_vec()))
     }
 }
 
 /// The manifest contains a count of the number of archived files, followed by an entry for each
 /// file. In all of the examples examined the order of the entries matches the order of the archived
 /// files, but whether this is a firm requirement or not is unknown.
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Manifest {
     /// The number of files in the far file.
     pub number_of_files: u32,
@@ -60,16 +255,23 @@ pub struct Manifest {
 
 /// A manifest entry containing the first file length, second file length, file offset, file name
 /// length, and file name.
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ManifestEntry {
-    file_path: String,
+    /// Deserialized entries aren't attached to a far file and can't be read.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    source: Source,
     /// The file length is stored twice. Perhaps this is because some variant of FAR files supports
     /// compressed data and the fields would hold the compressed and uncompressed sizes, but this is
     /// pure speculation. The safest thing to do is to leave the fields identical.
+    ///
+    /// In version three archives this is the decompressed size.
     pub file_length1: u32,
     /// The file length is stored twice. Perhaps this is because some variant of FAR files supports
     /// compressed data and the fields would hold the compressed and uncompressed sizes, but this is
     /// pure speculation. The safest thing to do is to leave the fields identical.
+    ///
+    /// In version three archives this is the compressed size, which is stored in 24 bits.
     pub file_length2: u32,
     /// The file offset is the byte offset from the beginning of the FAR file to the archived file.
     pub file_offset: u32,
@@ -77,94 +279,406 @@ pub struct ManifestEntry {
     /// terminating null. For example, the filename "foo" would have a filename length of three and
     /// the entry would be nineteen bytes long in total.
     pub file_name_length: u32,
-    /// The name of the file. This can include directories.
+    /// The name of the file. This can include directories. Names that aren't valid UTF-8 are
+    /// decoded as Windows-1252.
     pub file_name: String,
+    /// The name of the file exactly as it is stored in the manifest. This is what gets written
+    /// back when the archive is repacked.
+    pub file_name_bytes: Vec<u8>,
+    /// The fields only found in version three archives from The Sims Online.
+    pub far3: Option<Far3Fields>,
+}
+
+/// The manifest entry fields of version three archives. Files in these archives can be compressed
+/// with RefPack and are identified by a type and instance ID as well as by name. Unlike other
+/// Maxis formats there is no group ID.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Far3Fields {
+    /// The data type. 0x80 marks RefPack compressed data and 0x00 uncompressed data.
+    pub data_type: u8,
+    /// Whether the file is RefPack compressed.
+    pub compressed: bool,
+    /// An unknown byte that FreeSO calls the access number.
+    pub access_number: u8,
+    /// The type ID of the file.
+    pub type_id: u32,
+    /// The instance ID of the file, also called the file ID.
+    pub instance_id: u32,
 }
 
 impl ManifestEntry {
+    /// Read the contents of the file from the far file it was parsed from. Compressed files in
+    /// version three archives are decompressed.
     pub fn get_bytes(&self) -> Result<Vec<u8>, FarError> {
-        let mut f = File::open(self.file_path.as_str())?;
-        let mut buf: Vec<u8> = vec![0x00; self.file_length1 as usize];
-        f.seek(Start(self.file_offset as u64))?;
-        f.read_exact(&mut *buf)?;
-        return Ok(buf);
+        let stored = self.get_stored_bytes()?;
+        if !self.is_compressed() {
+            return Ok(stored);
+        }
+        decompress(&stored)
+    }
+
+    /// Read the contents of the file as they are stored in the far file, without decompressing.
+    pub fn get_stored_bytes(&self) -> Result<Vec<u8>, FarError> {
+        // read through the entry reader so a corrupt length fails at the end of the far file
+        // instead of allocating
+        let mut buf: Vec<u8> = vec![];
+        self.open_stored().read_to_end(&mut buf)?;
+        if buf.len() != self.stored_length() as usize {
+            return Err(FarError::FileError(io::ErrorKind::UnexpectedEof.into()));
+        }
+        Ok(buf)
+    }
+
+    /// Whether the file is RefPack compressed.
+    pub fn is_compressed(&self) -> bool {
+        self.far3.as_ref().is_some_and(|far3| far3.compressed)
+    }
+
+    /// The number of bytes the file takes up in the far file.
+    pub fn stored_length(&self) -> u32 {
+        if self.is_compressed() {
+            self.file_length2
+        } else {
+            self.file_length1
+        }
     }
 }
 
-fn parse_far(path: &str) -> Result<Far, FarError> {
+/// Decompress the stored bytes of a compressed file.
+pub(crate) fn decompress(stored: &[u8]) -> Result<Vec<u8>, FarError> {
+    // compressed files start with a nine byte header and the compressed size, followed by the
+    // RefPack stream
+    match stored.get(13..) {
+        Some(stream) if stream.starts_with(&[0x10, 0xFB]) => refpack::decompress(stream),
+        _ => refpack::decompress(stored),
+    }
+}
+
+fn parse_far(source: Source, options: &ParseOptions) -> Result<(Far, Vec<Diagnostic>), FarError> {
     let mut far = Far {
         signature: "".to_string(),
         version: 0,
+        variant: FarVariant::V1b,
         manifest_offset: 0,
         manifest: Manifest {
             number_of_files: 0,
             manifest_entries: vec![],
         },
+        index: NameIndex::default(),
+        source: source.clone(),
     };
+    let mut diagnostics: Vec<Diagnostic> = vec![];
 
-    let mut f = File::open(path)?;
+    let mut guard = source.lock()?;
+    let f = &mut *guard;
+    let file_length = f.seek(End(0))?;
+    if file_length < 16 {
+        return Err(FarError::TruncatedHeader { file_length });
+    }
+    f.seek(Start(0))?;
 
     // read signature
     let mut buf: [u8; 8] = [0x00; 8];
     f.read_exact(&mut buf)?;
-    far.signature = from_utf8(&buf)?.to_string();
+    if &buf != b"FAR!byAZ" {
+        let e = FarError::BadSignature {
+            signature: buf.to_vec(),
+            offset: 0,
+        };
+        report(&mut diagnostics, options, None, e)?;
+    }
+    far.signature = String::from_utf8_lossy(&buf).to_string();
 
     // read version
     let mut buf: [u8; 4] = [0x00; 4];
     f.read_exact(&mut buf)?;
-    far.version = u32::from_le_bytes(buf.try_into()?);
+    far.version = u32::from_le_bytes(buf);
+    if far.version != 1 && far.version != 3 {
+        let e = FarError::UnsupportedVersion {
+            version: far.version,
+            offset: 8,
+        };
+        report(&mut diagnostics, options, None, e)?;
+    }
 
     // read manifest offset
     f.read_exact(&mut buf)?;
-    far.manifest_offset = u32::from_le_bytes(buf.try_into()?);
+    far.manifest_offset = u32::from_le_bytes(buf);
+
+    // pick the manifest entry layout
+    let manifest_offset = far.manifest_offset as u64;
+    let mut manifest: Vec<u8> = vec![];
+    if far.version != 3 && manifest_offset + 4 <= file_length {
+        f.seek(Start(manifest_offset))?;
+        f.read_to_end(&mut manifest)?;
+    }
+    far.variant = match (far.version, options.variant) {
+        (3, _) => FarVariant::V3,
+        (_, Some(variant)) => variant,
+        (_, None) => detect_variant(&manifest, file_length).unwrap_or(FarVariant::V1b),
+    };
+
+    // look for the manifest elsewhere if the header doesn't point at something shaped like one,
+    // ignoring whether the entries point inside the far file
+    let recover = !options.strict && options.recover_manifest && far.version != 3;
+    if recover && detect_variant(&manifest, u64::MAX).is_none() {
+        f.seek(Start(0))?;
+        let mut bytes: Vec<u8> = vec![];
+        f.read_to_end(&mut bytes)?;
+        if let Some((offset, variant)) = find_manifest(&bytes) {
+            let e = FarError::ManifestOutOfBounds {
+                offset: manifest_offset,
+                file_length,
+            };
+            report(&mut diagnostics, options, None, e)?;
+            far.manifest_offset = offset;
+            far.variant = options.variant.unwrap_or(variant);
+        }
+    }
 
     // read manifest
-    f.seek(Start(far.manifest_offset as u64))?;
+    let manifest_offset = far.manifest_offset as u64;
+    if manifest_offset + 4 > file_length {
+        return Err(FarError::ManifestOutOfBounds {
+            offset: manifest_offset,
+            file_length,
+        });
+    }
+    f.seek(Start(manifest_offset))?;
     f.read_exact(&mut buf)?;
-    far.manifest.number_of_files = u32::from_le_bytes(buf.try_into()?);
+    far.manifest.number_of_files = u32::from_le_bytes(buf);
 
     // read manifest entries
-    for _ in 0..far.manifest.number_of_files {
-        let me = parse_manifest_entry(&mut f, path)?;
+    for index in 0..far.manifest.number_of_files {
+        let entry_offset = f.stream_position()?;
+        let me = match far.variant {
+            FarVariant::V3 => parse_far3_manifest_entry(f, &source),
+            variant => parse_manifest_entry(f, &source, variant),
+        };
+        let me = match me.map_err(|e| manifest_eof(e, entry_offset, file_length)) {
+            Ok(me) => me,
+            Err(e) => {
+                // the rest of the manifest can't be found after running out of bytes
+                let truncated = matches!(e, FarError::ManifestOutOfBounds { .. });
+                report(&mut diagnostics, options, Some(index), e)?;
+                if truncated {
+                    break;
+                }
+                continue;
+            }
+        };
+        if let Err(e) = check_manifest_entry(&me, entry_offset, file_length) {
+            // mismatched lengths are kept because the contents can still be read
+            let skip = matches!(e, FarError::EntryOutOfBounds { .. });
+            report(&mut diagnostics, options, Some(index), e)?;
+            if skip {
+                continue;
+            }
+        }
         far.manifest.manifest_entries.push(me);
     }
 
-    return Ok(far);
+    Ok((far, diagnostics))
+}
+
+/// Return `e` in strict mode, otherwise record it and carry on.
+fn report(
+    diagnostics: &mut Vec<Diagnostic>,
+    options: &ParseOptions,
+    entry: Option<u32>,
+    error: FarError,
+) -> Result<(), FarError> {
+    if options.strict {
+        return Err(error);
+    }
+    diagnostics.push(Diagnostic { entry, error });
+    Ok(())
+}
+
+/// Turn running out of bytes while reading a manifest entry into a ManifestOutOfBounds error.
+fn manifest_eof(e: FarError, offset: u64, file_length: u64) -> FarError {
+    match e {
+        FarError::FileError(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+            FarError::ManifestOutOfBounds {
+                offset,
+                file_length,
+            }
+        }
+        e => e,
+    }
+}
+
+/// Check that a manifest entry points inside the far file and, for version one archives, that
+/// both file lengths agree. `offset` is the position of the manifest entry. Bounds are checked
+/// first, so an entry that is out of bounds is always reported as such.
+fn check_manifest_entry(me: &ManifestEntry, offset: u64, file_length: u64) -> Result<(), FarError> {
+    if me.file_offset as u64 + me.stored_length() as u64 > file_length {
+        return Err(FarError::EntryOutOfBounds {
+            name: me.file_name.clone(),
+            offset: me.file_offset as u64,
+            len: me.stored_length() as u64,
+            file_length,
+        });
+    }
+    if me.far3.is_none() && me.file_length1 != me.file_length2 {
+        return Err(FarError::LengthMismatch {
+            name: me.file_name.clone(),
+            offset,
+            file_length1: me.file_length1,
+            file_length2: me.file_length2,
+        });
+    }
+    Ok(())
+}
+
+/// Work out whether a version one manifest uses 16 or 32-bit filename lengths by checking which
+/// layout keeps every entry inside the far file and has filenames without null bytes. 1b is
+/// preferred when both fit. Returns `None` when neither fits.
+fn detect_variant(manifest: &[u8], file_length: u64) -> Option<FarVariant> {
+    if manifest_length(manifest, FarVariant::V1b, file_length).is_some() {
+        Some(FarVariant::V1b)
+    } else if manifest_length(manifest, FarVariant::V1a, file_length).is_some() {
+        Some(FarVariant::V1a)
+    } else {
+        None
+    }
+}
+
+/// Search backwards from the end of the far file for a version one manifest that ends exactly at
+/// the end of the file, which is where the game's archives keep it.
+fn find_manifest(bytes: &[u8]) -> Option<(u32, FarVariant)> {
+    let file_length = bytes.len() as u64;
+    for offset in (16..bytes.len().saturating_sub(3)).rev() {
+        let manifest = &bytes[offset..];
+        if manifest[0..4] == [0x00; 4] {
+            continue;
+        }
+        for variant in [FarVariant::V1b, FarVariant::V1a] {
+            if manifest_length(manifest, variant, file_length) == Some(manifest.len()) {
+                return Some((offset as u32, variant));
+            }
+        }
+    }
+    None
+}
+
+/// The number of bytes a version one manifest takes up if every entry fits inside the far file,
+/// or `None` if it doesn't fit.
+fn manifest_length(manifest: &[u8], variant: FarVariant, file_length: u64) -> Option<usize> {
+    let name_length_width = if variant == FarVariant::V1a { 2 } else { 4 };
+    let count = manifest.get(0..4)?;
+    let count = u32::from_le_bytes([count[0], count[1], count[2], count[3]]);
+    let mut pos = 4;
+    for _ in 0..count {
+        let fields = manifest.get(pos..pos + 12 + name_length_width)?;
+        let file_length1 = u32::from_le_bytes([fields[0], fields[1], fields[2], fields[3]]);
+        let file_offset = u32::from_le_bytes([fields[8], fields[9], fields[10], fields[11]]);
+        let file_name_length = match name_length_width {
+            2 => u16::from_le_bytes([fields[12], fields[13]]) as usize,
+            _ => u32::from_le_bytes([fields[12], fields[13], fields[14], fields[15]]) as usize,
+        };
+        if file_offset as u64 + file_length1 as u64 > file_length {
+            return None;
+        }
+        pos += fields.len();
+        let file_name = manifest.get(pos..pos + file_name_length)?;
+        if file_name.contains(&0x00) {
+            return None;
+        }
+        pos += file_name_length;
+    }
+    Some(pos)
 }
 
-fn parse_manifest_entry(f: &mut File, uigraphics_path: &str) -> Result<ManifestEntry, FarError> {
+fn parse_manifest_entry(
+    f: &mut dyn ReadSeek,
+    source: &Source,
+    variant: FarVariant,
+) -> Result<ManifestEntry, FarError> {
     let mut me = ManifestEntry {
-        file_path: uigraphics_path.to_string(),
+        source: source.clone(),
         file_length1: 0,
         file_length2: 0,
         file_offset: 0,
         file_name_length: 0,
         file_name: "".to_string(),
+        file_name_bytes: vec![],
+        far3: None,
     };
     let mut buf: [u8; 4] = [0x00; 4];
 
     // read file length 1
     f.read_exact(&mut buf)?;
-    me.file_length1 = u32::from_le_bytes(buf.try_into()?);
+    me.file_length1 = u32::from_le_bytes(buf);
 
     // read file length 2
     f.read_exact(&mut buf)?;
-    me.file_length2 = u32::from_le_bytes(buf.try_into()?);
+    me.file_length2 = u32::from_le_bytes(buf);
 
     // read file offset
     f.read_exact(&mut buf)?;
-    me.file_offset = u32::from_le_bytes(buf.try_into()?);
+    me.file_offset = u32::from_le_bytes(buf);
 
     // read file name length
-    f.read_exact(&mut buf)?;
-    me.file_name_length = u32::from_le_bytes(buf.try_into()?);
+    if variant == FarVariant::V1a {
+        let mut buf: [u8; 2] = [0x00; 2];
+        f.read_exact(&mut buf)?;
+        me.file_name_length = u16::from_le_bytes(buf) as u32;
+    } else {
+        f.read_exact(&mut buf)?;
+        me.file_name_length = u32::from_le_bytes(buf);
+    }
 
     // read file name
-    let mut buf: Vec<u8> = vec![0x00; me.file_name_length as usize];
+    me.file_name_bytes = read_file_name(f, me.file_name_length)?;
+    me.file_name = cp1252::decode(&me.file_name_bytes);
+
+    Ok(me)
+}
+
+fn read_file_name(f: &mut dyn ReadSeek, file_name_length: u32) -> Result<Vec<u8>, FarError> {
+    // read through take so a garbage length fails at the end of the file instead of allocating
+    let mut buf: Vec<u8> = vec![];
+    f.take(file_name_length as u64).read_to_end(&mut buf)?;
+    if buf.len() != file_name_length as usize {
+        return Err(FarError::FileError(io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(buf)
+}
+
+fn parse_far3_manifest_entry(
+    f: &mut dyn ReadSeek,
+    source: &Source,
+) -> Result<ManifestEntry, FarError> {
+    let mut buf: [u8; 24] = [0x00; 24];
     f.read_exact(&mut buf)?;
-    me.file_name = from_utf8(&buf)?.to_string();
 
-    return Ok(me);
+    let far3 = Far3Fields {
+        data_type: buf[7],
+        compressed: buf[12] == 0x01,
+        access_number: buf[13],
+        type_id: u32::from_le_bytes([buf[16], buf[17], buf[18], buf[19]]),
+        instance_id: u32::from_le_bytes([buf[20], buf[21], buf[22], buf[23]]),
+    };
+    let mut me = ManifestEntry {
+        source: source.clone(),
+        // decompressed size
+        file_length1: u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]),
+        // compressed size
+        file_length2: u32::from_le_bytes([buf[4], buf[5], buf[6], 0x00]),
+        file_offset: u32::from_le_bytes([buf[8], buf[9], buf[10], buf[11]]),
+        file_name_length: u16::from_le_bytes([buf[14], buf[15]]) as u32,
+        file_name: "".to_string(),
+        file_name_bytes: vec![],
+        far3: Some(far3),
+    };
+
+    // read file name
+    me.file_name_bytes = read_file_name(f, me.file_name_length)?;
+    me.file_name = cp1252::decode(&me.file_name_bytes);
+
+    Ok(me)
 }
 
 #[cfg(test)]
@@ -177,6 +691,7 @@ mod tests {
         let far = Far::new(path).unwrap();
         assert_eq!(far.signature, "FAR!byAZ");
         assert_eq!(far.version, 1);
+        assert_eq!(far.variant, FarVariant::V1b);
         assert_eq!(far.manifest_offset, 160);
         assert_eq!(far.manifest.number_of_files, 1);
         assert_eq!(far.manifest.manifest_entries[0].file_length1, 144);
@@ -198,4 +713,258 @@ mod tests {
             144
         );
     }
+
+    #[test]
+    fn test_from_bytes() {
+        let bytes = std::fs::read("test.far").unwrap();
+        let far = Far::from_bytes(&bytes).unwrap();
+        assert_eq!(far.manifest.manifest_entries[0].file_name, "test.bmp");
+        assert_eq!(
+            far.manifest.manifest_entries[0].get_bytes().unwrap(),
+            &bytes[16..160]
+        );
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let bytes = std::fs::read("test.far").unwrap();
+        let far = Far::from_reader(Cursor::new(bytes)).unwrap();
+        let entry = far.manifest.manifest_entries[0].clone();
+        drop(far);
+        assert_eq!(entry.get_bytes().unwrap().len(), 144);
+    }
+
+    #[test]
+    fn test_cp1252_file_name() {
+        let mut bytes = far_bytes(1, &[(4, 4, 16, "Gr\u{F6}\u{DF}e.bmp")]);
+        // replace the UTF-8 name with the Windows-1252 one
+        let len = bytes.len();
+        bytes.splice(len - 11.., b"Gr\xF6\xDFe.bmp".to_vec());
+        bytes[len - 15] = 9;
+
+        let far = Far::from_bytes(&bytes).unwrap();
+        let me = &far.manifest.manifest_entries[0];
+        assert_eq!(me.file_name, "Gr\u{F6}\u{DF}e.bmp");
+        assert_eq!(me.file_name_bytes, b"Gr\xF6\xDFe.bmp");
+
+        let mut rewritten: Vec<u8> = vec![];
+        far.write_to(&mut rewritten).unwrap();
+        assert_eq!(rewritten, bytes);
+    }
+
+    fn far_bytes(version: u32, entries: &[(u32, u32, u32, &str)]) -> Vec<u8> {
+        let mut bytes = b"FAR!byAZ".to_vec();
+        bytes.extend_from_slice(&version.to_le_bytes());
+        bytes.extend_from_slice(&20u32.to_le_bytes());
+        bytes.extend_from_slice(b"abcd");
+        bytes.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        for (file_length1, file_length2, file_offset, file_name) in entries {
+            bytes.extend_from_slice(&file_length1.to_le_bytes());
+            bytes.extend_from_slice(&file_length2.to_le_bytes());
+            bytes.extend_from_slice(&file_offset.to_le_bytes());
+            bytes.extend_from_slice(&(file_name.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(file_name.as_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_errors() {
+        let bytes = far_bytes(1, &[(4, 4, 16, "a.txt")]);
+        assert!(Far::from_bytes(&bytes).is_ok());
+
+        let e = Far::from_bytes(&bytes[0..10]).err().unwrap();
+        assert!(matches!(e, FarError::TruncatedHeader { file_length: 10 }));
+
+        let mut bad_signature = bytes.clone();
+        bad_signature[0] = b'R';
+        let e = Far::from_bytes(&bad_signature).err().unwrap();
+        assert!(matches!(e, FarError::BadSignature { offset: 0, .. }));
+
+        let e = Far::from_bytes(&far_bytes(2, &[])).err().unwrap();
+        assert!(matches!(
+            e,
+            FarError::UnsupportedVersion {
+                version: 2,
+                offset: 8
+            }
+        ));
+
+        let mut bad_manifest_offset = bytes.clone();
+        bad_manifest_offset[12] = 0xFF;
+        let e = Far::from_bytes(&bad_manifest_offset).err().unwrap();
+        assert!(matches!(
+            e,
+            FarError::ManifestOutOfBounds { offset: 255, .. }
+        ));
+
+        let e = Far::from_bytes(&bytes[0..bytes.len() - 1]).err().unwrap();
+        assert!(matches!(
+            e,
+            FarError::ManifestOutOfBounds { offset: 24, .. }
+        ));
+
+        let e = Far::from_bytes(&far_bytes(1, &[(40, 40, 16, "a.txt")]))
+            .err()
+            .unwrap();
+        assert!(matches!(
+            e,
+            FarError::EntryOutOfBounds {
+                offset: 16,
+                len: 40,
+                ..
+            }
+        ));
+
+        let e = Far::from_bytes(&far_bytes(1, &[(4, 3, 16, "a.txt")]))
+            .err()
+            .unwrap();
+        assert!(matches!(e, FarError::LengthMismatch { offset: 24, .. }));
+    }
+
+    fn lenient(bytes: &[u8]) -> Result<(Far, Vec<Diagnostic>), FarError> {
+        let options = ParseOptions {
+            strict: false,
+            recover_manifest: true,
+            ..ParseOptions::default()
+        };
+        Far::from_reader_with_diagnostics(Cursor::new(bytes.to_vec()), &options)
+    }
+
+    #[test]
+    fn test_lenient() {
+        let entries = [
+            (4, 4, 16, "a.txt"),
+            (400, 400, 16, "b.txt"),
+            (2, 2, 18, "c.txt"),
+            (4000, 3999, 16, "bad.txt"),
+        ];
+        let (far, diagnostics) = lenient(&far_bytes(1, &entries)).unwrap();
+        assert_eq!(far.manifest.number_of_files, 4);
+        let names: Vec<&str> = far
+            .manifest
+            .manifest_entries
+            .iter()
+            .map(|me| me.file_name.as_str())
+            .collect();
+        assert_eq!(names, ["a.txt", "c.txt"]);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].entry, Some(1));
+        assert_eq!(diagnostics[1].entry, Some(3));
+        for diagnostic in &diagnostics {
+            assert!(matches!(
+                diagnostic.error,
+                FarError::EntryOutOfBounds { .. }
+            ));
+        }
+
+        // a truncated manifest keeps the entries before the end of the file
+        let bytes = far_bytes(1, &[(4, 4, 16, "a.txt"), (2, 2, 18, "c.txt")]);
+        let (far, diagnostics) = lenient(&bytes[0..bytes.len() - 2]).unwrap();
+        assert_eq!(far.manifest.manifest_entries.len(), 1);
+        assert_eq!(diagnostics[0].entry, Some(1));
+    }
+
+    #[test]
+    fn test_recover_manifest() {
+        let mut bytes = far_bytes(1, &[(4, 4, 16, "a.txt")]);
+        bytes[12] = 0xFF;
+        assert!(Far::from_bytes(&bytes).is_err());
+
+        let (far, diagnostics) = lenient(&bytes).unwrap();
+        assert_eq!(far.manifest_offset, 20);
+        assert_eq!(
+            far.manifest.manifest_entries[0].get_bytes().unwrap(),
+            b"abcd"
+        );
+        assert_eq!(diagnostics.len(), 1);
+        assert!(matches!(
+            diagnostics[0].error,
+            FarError::ManifestOutOfBounds { offset: 255, .. }
+        ));
+    }
+
+    #[test]
+    fn test_1a() {
+        let mut bytes = b"FAR!byAZ".to_vec();
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&20u32.to_le_bytes());
+        bytes.extend_from_slice(b"abcd");
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&4u32.to_le_bytes());
+        bytes.extend_from_slice(&4u32.to_le_bytes());
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&9u16.to_le_bytes());
+        bytes.extend_from_slice(b"dir\\a.txt");
+
+        let far = Far::from_bytes(&bytes).unwrap();
+        assert_eq!(far.variant, FarVariant::V1a);
+        assert_eq!(far.manifest.manifest_entries[0].file_name_length, 9);
+        assert_eq!(far.manifest.manifest_entries[0].file_name, "dir\\a.txt");
+        assert_eq!(
+            far.manifest.manifest_entries[0].get_bytes().unwrap(),
+            b"abcd"
+        );
+
+        let options = ParseOptions {
+            variant: Some(FarVariant::V1b),
+            ..ParseOptions::default()
+        };
+        let far = Far::from_reader_with_options(Cursor::new(bytes), &options);
+        assert!(far.is_err());
+    }
+
+    fn far3_entry(
+        manifest: &mut Vec<u8>,
+        lengths: (u32, u32),
+        offset: u32,
+        compressed: bool,
+        ids: (u32, u32),
+        name: &str,
+    ) {
+        manifest.extend_from_slice(&lengths.0.to_le_bytes());
+        manifest.extend_from_slice(&lengths.1.to_le_bytes()[0..3]);
+        manifest.push(if compressed { 0x80 } else { 0x00 });
+        manifest.extend_from_slice(&offset.to_le_bytes());
+        manifest.push(compressed as u8);
+        manifest.push(0x00);
+        manifest.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        manifest.extend_from_slice(&ids.0.to_le_bytes());
+        manifest.extend_from_slice(&ids.1.to_le_bytes());
+        manifest.extend_from_slice(name.as_bytes());
+    }
+
+    #[test]
+    fn test_far3() {
+        let raw = b"hello".to_vec();
+        let mut compressed = vec![0x00; 9];
+        compressed.extend_from_slice(&13u32.to_le_bytes());
+        compressed.extend_from_slice(&[
+            0x10, 0xFB, 0x00, 0x00, 0x0C, 0xE0, b'a', b'b', b'c', b'd', 0x14, 0x03, 0xFC,
+        ]);
+
+        let mut bytes = b"FAR!byAZ".to_vec();
+        bytes.extend_from_slice(&3u32.to_le_bytes());
+        let manifest_offset = 16 + raw.len() + compressed.len();
+        bytes.extend_from_slice(&(manifest_offset as u32).to_le_bytes());
+        bytes.extend_from_slice(&raw);
+        bytes.extend_from_slice(&compressed);
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        far3_entry(&mut bytes, (5, 5), 16, false, (1, 2), "raw.txt");
+        let lengths = (12, compressed.len() as u32);
+        far3_entry(&mut bytes, lengths, 21, true, (3, 4), "packed.txt");
+
+        let far = Far::from_bytes(&bytes).unwrap();
+        assert_eq!(far.version, 3);
+        let entries = &far.manifest.manifest_entries;
+        assert_eq!(entries[0].file_name, "raw.txt");
+        assert!(!entries[0].is_compressed());
+        assert_eq!(entries[0].get_bytes().unwrap(), b"hello");
+        assert_eq!(entries[1].file_name, "packed.txt");
+        assert!(entries[1].is_compressed());
+        assert_eq!(entries[1].stored_length(), 26);
+        let far3 = entries[1].far3.as_ref().unwrap();
+        assert_eq!((far3.type_id, far3.instance_id), (3, 4));
+        assert_eq!(entries[1].get_bytes().unwrap(), b"abcdabcdabcd");
+    }
 }
@@ -0,0 +1,92 @@
+use crate::{decompress, Far, FarError, ManifestEntry};
+use memmap2::Mmap;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::Cursor;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// A far file that is memory mapped instead of read through a file handle. Entries are returned
+/// as slices of the mapping, so reading them does not open, seek or copy anything.
+pub struct MmapFar {
+    mmap: Arc<Mmap>,
+    far: Far,
+}
+
+/// Lets the parser and the manifest entries read from the mapping through a Cursor.
+struct SharedMmap(Arc<Mmap>);
+
+impl AsRef<[u8]> for SharedMmap {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Far {
+    /// Memory map the far file at `path` and parse it.
+    ///
+    /// The file must not be modified or truncated while it is mapped. Doing so can make reads
+    /// return changed data or crash the process.
+    pub fn open_mmap(path: &str) -> Result<MmapFar, FarError> {
+        let f = File::open(path)?;
+        // SAFETY: the mapping is read only. The caller is told not to modify the file while it is
+        // mapped, which is the same requirement every memory mapped reader has.
+        let mmap = Arc::new(unsafe { Mmap::map(&f)? });
+        let far = Far::from_reader(Cursor::new(SharedMmap(mmap.clone())))?;
+        Ok(MmapFar { mmap, far })
+    }
+}
+
+impl MmapFar {
+    /// The parsed far file.
+    pub fn far(&self) -> &Far {
+        &self.far
+    }
+
+    /// The contents of `entry` as they are stored in the far file, without decompressing.
+    pub fn get_stored_bytes(&self, entry: &ManifestEntry) -> Result<&[u8], FarError> {
+        let start = entry.file_offset as usize;
+        let end = start + entry.stored_length() as usize;
+        self.mmap
+            .get(start..end)
+            .ok_or_else(|| FarError::EntryOutOfBounds {
+                name: entry.file_name.clone(),
+                offset: entry.file_offset as u64,
+                len: entry.stored_length() as u64,
+                file_length: self.mmap.len() as u64,
+            })
+    }
+
+    /// The contents of `entry`. Uncompressed files are borrowed from the mapping and compressed
+    /// files are decompressed into a new buffer.
+    pub fn get_bytes(&self, entry: &ManifestEntry) -> Result<Cow<'_, [u8]>, FarError> {
+        let stored = self.get_stored_bytes(entry)?;
+        if entry.is_compressed() {
+            return Ok(Cow::Owned(decompress(stored)?));
+        }
+        Ok(Cow::Borrowed(stored))
+    }
+}
+
+impl Deref for MmapFar {
+    type Target = Far;
+
+    fn deref(&self) -> &Far {
+        &self.far
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_mmap() {
+        let far = Far::open_mmap("test.far").unwrap();
+        let entry = &far.manifest.manifest_entries[0];
+        let bytes = far.get_bytes(entry).unwrap();
+        assert!(matches!(bytes, Cow::Borrowed(_)));
+        assert_eq!(&*bytes, &std::fs::read("test.far").unwrap()[16..160]);
+        assert_eq!(entry.get_bytes().unwrap(), &*bytes);
+    }
+}
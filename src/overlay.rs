@@ -0,0 +1,190 @@
+use crate::{normalize_name, Far, ManifestEntry};
+use std::collections::HashMap;
+
+/// Several far files mounted on top of each other, like the game loads the base game and then
+/// each expansion pack. A file in a later far file overrides a file with the same name in an
+/// earlier one. Names are matched like [`Far::get`], so within one far file the first entry with
+/// a name wins.
+#[derive(Clone, Debug, Default)]
+pub struct FarStack {
+    layers: Vec<Layer>,
+}
+
+#[derive(Clone, Debug)]
+struct Layer {
+    name: String,
+    far: Far,
+}
+
+/// A file in a [`FarStack`] and the far file it came from.
+#[derive(Clone, Copy, Debug)]
+pub struct Resolved<'a> {
+    /// The index of the far file, in mount order.
+    pub layer: usize,
+    /// The name the far file was mounted with.
+    pub layer_name: &'a str,
+    /// The manifest entry.
+    pub entry: &'a ManifestEntry,
+}
+
+/// A file hidden by another file with the same name.
+#[derive(Clone, Copy, Debug)]
+pub struct Shadowed<'a> {
+    /// The hidden file.
+    pub hidden: Resolved<'a>,
+    /// The index of the far file holding the file that wins. This is the same far file as the
+    /// hidden file when its manifest has the name more than once.
+    pub shadowed_by: usize,
+}
+
+impl FarStack {
+    /// Create an empty stack.
+    pub fn new() -> FarStack {
+        FarStack { layers: vec![] }
+    }
+
+    /// Mount `far` on top of the far files already mounted, so its files win. `name` identifies
+    /// the far file in [`Resolved`], for example its path. Returns its index.
+    pub fn mount(&mut self, name: &str, far: Far) -> usize {
+        self.layers.push(Layer {
+            name: name.to_string(),
+            far,
+        });
+        self.layers.len() - 1
+    }
+
+    /// The mounted far files with their names, in mount order.
+    pub fn layers(&self) -> impl Iterator<Item = (&str, &Far)> {
+        self.layers.iter().map(|l| (l.name.as_str(), &l.far))
+    }
+
+    /// The winning manifest entry for `name`.
+    pub fn get(&self, name: &str) -> Option<&ManifestEntry> {
+        self.resolve(name).map(|resolved| resolved.entry)
+    }
+
+    /// Whether any mounted far file has a file called `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.resolve(name).is_some()
+    }
+
+    /// The winning manifest entry for `name` and the far file it came from.
+    pub fn resolve(&self, name: &str) -> Option<Resolved<'_>> {
+        self.layers
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, l)| l.far.get(name).map(|me| self.resolved(i, me)))
+    }
+
+    /// Every winning file, one per name, in mount order and then manifest order.
+    pub fn entries(&self) -> Vec<Resolved<'_>> {
+        let winners = self.winners();
+        self.all()
+            .filter(|(position, name)| winners[name] == *position)
+            .map(|((layer, i), _)| {
+                self.resolved(layer, &self.layers[layer].far.manifest.manifest_entries[i])
+            })
+            .collect()
+    }
+
+    /// Every file hidden by another file with the same name, in mount order and then manifest
+    /// order.
+    pub fn shadowed(&self) -> Vec<Shadowed<'_>> {
+        let winners = self.winners();
+        self.all()
+            .filter_map(|((layer, i), name)| {
+                let winner = winners[&name];
+                (winner != (layer, i)).then(|| Shadowed {
+                    hidden: self
+                        .resolved(layer, &self.layers[layer].far.manifest.manifest_entries[i]),
+                    shadowed_by: winner.0,
+                })
+            })
+            .collect()
+    }
+
+    /// Every entry's position and normalized name, in mount order and then manifest order.
+    fn all(&self) -> impl Iterator<Item = ((usize, usize), String)> + '_ {
+        self.layers.iter().enumerate().flat_map(|(layer, l)| {
+            l.far
+                .manifest
+                .manifest_entries
+                .iter()
+                .enumerate()
+                .map(move |(i, me)| ((layer, i), normalize_name(&me.file_name)))
+        })
+    }
+
+    /// Normalized names to the position of the entry that wins.
+    fn winners(&self) -> HashMap<String, (usize, usize)> {
+        let mut winners: HashMap<String, (usize, usize)> = HashMap::new();
+        for (position, name) in self.all() {
+            // a later far file overrides, but within a far file the first entry wins
+            match winners.get(&name) {
+                Some(winner) if winner.0 == position.0 => {}
+                _ => {
+                    winners.insert(name, position);
+                }
+            }
+        }
+        winners
+    }
+
+    fn resolved<'a>(&'a self, layer: usize, entry: &'a ManifestEntry) -> Resolved<'a> {
+        Resolved {
+            layer,
+            layer_name: &self.layers[layer].name,
+            entry,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::test_far as far;
+
+    #[test]
+    fn test_stack() {
+        let mut stack = FarStack::new();
+        stack.mount(
+            "base.far",
+            far(&[("Buttons\\ok.bmp", b"base"), ("test.bmp", b"test")]),
+        );
+        stack.mount(
+            "expansion.far",
+            far(&[("buttons/OK.bmp", b"expansion"), ("buttons/ok.bmp", b"dup")]),
+        );
+
+        let resolved = stack.resolve("BUTTONS/ok.bmp").unwrap();
+        assert_eq!(resolved.layer, 1);
+        assert_eq!(resolved.layer_name, "expansion.far");
+        assert_eq!(resolved.entry.get_bytes().unwrap(), b"expansion");
+        assert_eq!(stack.get("test.bmp").unwrap().get_bytes().unwrap(), b"test");
+        assert!(!stack.contains("missing.bmp"));
+
+        let entries: Vec<(usize, &str)> = stack
+            .entries()
+            .iter()
+            .map(|r| (r.layer, r.entry.file_name.as_str()))
+            .collect();
+        assert_eq!(entries, [(0, "test.bmp"), (1, "buttons/OK.bmp")]);
+
+        let shadowed: Vec<(usize, &str, usize)> = stack
+            .shadowed()
+            .iter()
+            .map(|s| {
+                (
+                    s.hidden.layer,
+                    s.hidden.entry.file_name.as_str(),
+                    s.shadowed_by,
+                )
+            })
+            .collect();
+        assert_eq!(
+            shadowed,
+            [(0, "Buttons\\ok.bmp", 1), (1, "buttons/ok.bmp", 1)]
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use crate::FarError;
+
+/// Decompress a RefPack (also known as QFS) stream, starting at its two-byte `0x10FB` header.
+///
+/// The header is followed by the decompressed size as a big-endian 24-bit integer (32-bit when
+/// the high bit of the first byte is set). If the lowest bit of the first byte is set, the
+/// compressed size is stored first and is skipped.
+pub(crate) fn decompress(data: &[u8]) -> Result<Vec<u8>, FarError> {
+    if data.len() < 2 || data[0] & 0x3E != 0x10 || data[1] != 0xFB {
+        return Err(refpack_error("missing 0x10FB header"));
+    }
+    let size_width = if data[0] & 0x80 != 0 { 4 } else { 3 };
+    let mut pos = 2;
+    if data[0] & 0x01 != 0 {
+        pos += size_width;
+    }
+    let size_bytes = data
+        .get(pos..pos + size_width)
+        .ok_or_else(|| refpack_error("truncated header"))?;
+    let size = size_bytes
+        .iter()
+        .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+    pos += size_width;
+
+    // don't trust the header with a large allocation up front
+    let mut out: Vec<u8> = Vec::with_capacity(size.min(data.len() * 8));
+    while pos < data.len() {
+        let b0 = data[pos] as usize;
+        let (literal, copy, offset, width) = if b0 < 0x80 {
+            let b1 = byte(data, pos + 1)?;
+            (
+                b0 & 0x03,
+                ((b0 & 0x1C) >> 2) + 3,
+                ((b0 & 0x60) << 3) + b1 + 1,
+                2,
+            )
+        } else if b0 < 0xC0 {
+            let b1 = byte(data, pos + 1)?;
+            let b2 = byte(data, pos + 2)?;
+            (b1 >> 6, (b0 & 0x3F) + 4, ((b1 & 0x3F) << 8) + b2 + 1, 3)
+        } else if b0 < 0xE0 {
+            let b1 = byte(data, pos + 1)?;
+            let b2 = byte(data, pos + 2)?;
+            let b3 = byte(data, pos + 3)?;
+            let copy = ((b0 & 0x0C) << 6) + b3 + 5;
+            (b0 & 0x03, copy, ((b0 & 0x10) << 12) + (b1 << 8) + b2 + 1, 4)
+        } else if b0 < 0xFC {
+            (((b0 & 0x1F) << 2) + 4, 0, 0, 1)
+        } else {
+            // end of stream, with up to three trailing literal bytes
+            (b0 & 0x03, 0, 0, 1)
+        };
+        pos += width;
+
+        // copy literal bytes from the input
+        let literal_bytes = data
+            .get(pos..pos + literal)
+            .ok_or_else(|| refpack_error("literal runs past the end of the stream"))?;
+        out.extend_from_slice(literal_bytes);
+        pos += literal;
+
+        // copy previously decompressed bytes, which may overlap the bytes being written
+        if copy > 0 {
+            if offset > out.len() {
+                return Err(refpack_error(
+                    "back reference before the start of the output",
+                ));
+            }
+            let start = out.len() - offset;
+            for i in 0..copy {
+                out.push(out[start + i]);
+            }
+        }
+
+        if out.len() > size {
+            return Err(refpack_error("output is larger than the header says"));
+        }
+        if b0 >= 0xFC {
+            break;
+        }
+    }
+
+    if out.len() != size {
+        return Err(refpack_error("output is smaller than the header says"));
+    }
+    Ok(out)
+}
+
+fn byte(data: &[u8], pos: usize) -> Result<usize, FarError> {
+    data.get(pos)
+        .map(|b| *b as usize)
+        .ok_or_else(|| refpack_error("command runs past the end of the stream"))
+}
+
+fn refpack_error(message: &str) -> FarError {
+    FarError::RefPackError(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompress() {
+        // four literal bytes, then copy eight bytes from four bytes back, then stop
+        let data = [
+            0x10, 0xFB, 0x00, 0x00, 0x0C, 0xE0, b'a', b'b', b'c', b'd', 0x14, 0x03, 0xFC,
+        ];
+        assert_eq!(decompress(&data).unwrap(), b"abcdabcdabcd");
+    }
+
+    #[test]
+    fn test_decompress_bad_header() {
+        assert!(decompress(&[0x00, 0x00, 0x00]).is_err());
+    }
+}
@@ -0,0 +1,48 @@
+use std::fmt;
+use std::io;
+use std::io::{Cursor, Read, Seek};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Anything a far file can be parsed from.
+pub(crate) trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+/// A shared handle to the far file. The handle is cloned into every manifest entry so the
+/// contents of an entry can be read back without reopening the far file. Handles are left out of
+/// comparisons and debug output, so entries compare by their manifest fields.
+#[derive(Clone)]
+pub(crate) struct Source(Arc<Mutex<dyn ReadSeek>>);
+
+impl Default for Source {
+    /// A handle to an empty far file, for entries that aren't attached to one.
+    fn default() -> Source {
+        Source::new(Cursor::new(vec![]))
+    }
+}
+
+impl fmt::Debug for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Source")
+    }
+}
+
+impl PartialEq for Source {
+    fn eq(&self, _: &Source) -> bool {
+        true
+    }
+}
+
+impl Source {
+    pub(crate) fn new<R: Read + Seek + Send + 'static>(reader: R) -> Source {
+        Source(Arc::new(Mutex::new(reader)))
+    }
+
+    /// Lock the handle for reading. Seeking moves the position for every holder of the handle,
+    /// so always seek before reading.
+    pub(crate) fn lock(&self) -> io::Result<MutexGuard<'_, dyn ReadSeek + 'static>> {
+        self.0
+            .lock()
+            .map_err(|_| io::Error::other("far file handle was poisoned by a panicking reader"))
+    }
+}
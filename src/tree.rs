@@ -0,0 +1,205 @@
+use crate::{normalize_name, Far};
+use std::collections::BTreeMap;
+
+/// A directory tree built from the directories in the manifest entry file names. Both `/` and `\`
+/// separate directories and paths are matched case-insensitively, like [`Far::get`]. A directory
+/// is shown with the spelling of the first file name it appears in.
+#[derive(Clone, Debug)]
+pub struct DirTree {
+    root: Node,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Node {
+    name: String,
+    children: BTreeMap<String, Node>,
+    entry: Option<usize>,
+}
+
+/// A file or directory in a [`DirTree`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirEntry {
+    /// The last component of the path.
+    pub name: String,
+    /// The path from the root of the tree, separated by `/`.
+    pub path: String,
+    /// The index of the file in `manifest.manifest_entries`, or `None` for directories.
+    pub entry: Option<usize>,
+}
+
+impl DirEntry {
+    /// Whether this is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.entry.is_none()
+    }
+}
+
+impl Far {
+    /// Build a directory tree from the manifest entry file names.
+    pub fn tree(&self) -> DirTree {
+        let mut root = Node::default();
+        for (i, me) in self.manifest.manifest_entries.iter().enumerate() {
+            let mut node = &mut root;
+            let mut depth = 0;
+            for part in components(&me.file_name) {
+                depth += 1;
+                node = node
+                    .children
+                    .entry(normalize_name(part))
+                    .or_insert_with(|| Node {
+                        name: part.to_string(),
+                        ..Node::default()
+                    });
+            }
+            // the first entry with a name wins, like Far::get
+            if node.entry.is_none() && depth > 0 {
+                node.entry = Some(i);
+            }
+        }
+        DirTree { root }
+    }
+}
+
+impl DirTree {
+    /// The files and directories directly inside the directory at `path`, sorted by normalized
+    /// name. An empty path is the root. Returns `None` if `path` isn't a directory.
+    pub fn read_dir(&self, path: &str) -> Option<Vec<DirEntry>> {
+        let (node, prefix) = self.find(path)?;
+        if !std::ptr::eq(node, &self.root) && node.children.is_empty() {
+            return None;
+        }
+        let mut entries: Vec<DirEntry> = vec![];
+        for child in node.children.values() {
+            let path = if prefix.is_empty() {
+                child.name.clone()
+            } else {
+                format!("{}/{}", prefix, child.name)
+            };
+            // a name used for both a file and a directory is listed twice
+            if !child.children.is_empty() {
+                entries.push(DirEntry {
+                    name: child.name.clone(),
+                    path: path.clone(),
+                    entry: None,
+                });
+            }
+            if let Some(entry) = child.entry {
+                entries.push(DirEntry {
+                    name: child.name.clone(),
+                    path,
+                    entry: Some(entry),
+                });
+            }
+        }
+        Some(entries)
+    }
+
+    /// Whether `path` is a directory. The root, the empty path, is always a directory.
+    pub fn is_dir(&self, path: &str) -> bool {
+        match self.find(path) {
+            Some((node, _)) => std::ptr::eq(node, &self.root) || !node.children.is_empty(),
+            None => false,
+        }
+    }
+
+    /// Whether `path` is a file.
+    pub fn is_file(&self, path: &str) -> bool {
+        self.find(path)
+            .is_some_and(|(node, _)| node.entry.is_some())
+    }
+
+    /// Every file in the tree with its path, sorted by normalized path, using the same spelling
+    /// for each directory. This is the layout to recreate when extracting.
+    pub fn files(&self) -> Vec<DirEntry> {
+        let mut files: Vec<DirEntry> = vec![];
+        walk(&self.root, "", &mut files);
+        files
+    }
+
+    /// Find the node at `path` and its path as spelled in the tree.
+    fn find(&self, path: &str) -> Option<(&Node, String)> {
+        let mut node = &self.root;
+        let mut spelled: Vec<&str> = vec![];
+        for part in components(path) {
+            node = node.children.get(&normalize_name(part))?;
+            spelled.push(&node.name);
+        }
+        Some((node, spelled.join("/")))
+    }
+}
+
+fn walk(node: &Node, prefix: &str, files: &mut Vec<DirEntry>) {
+    for child in node.children.values() {
+        let path = if prefix.is_empty() {
+            child.name.clone()
+        } else {
+            format!("{}/{}", prefix, child.name)
+        };
+        walk(child, &path, files);
+        if let Some(entry) = child.entry {
+            files.push(DirEntry {
+                name: child.name.clone(),
+                path,
+                entry: Some(entry),
+            });
+        }
+    }
+}
+
+/// The directory components of a file name, skipping empty and `.` components.
+fn components(name: &str) -> impl Iterator<Item = &str> {
+    name.split(['/', '\\'])
+        .filter(|part| !part.is_empty() && *part != ".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::test_far;
+
+    fn far() -> Far {
+        test_far(&[
+            ("Buttons\\Btn_ok.bmp", b""),
+            ("buttons/Dialogs/frame.bmp", b""),
+            ("test.bmp", b""),
+        ])
+    }
+
+    #[test]
+    fn test_read_dir() {
+        let tree = far().tree();
+        let root = tree.read_dir("").unwrap();
+        let names: Vec<(&str, bool)> = root.iter().map(|e| (e.name.as_str(), e.is_dir())).collect();
+        assert_eq!(names, [("Buttons", true), ("test.bmp", false)]);
+
+        let buttons = tree.read_dir("BUTTONS").unwrap();
+        assert_eq!(buttons[0].entry, Some(0));
+        assert_eq!(buttons[0].path, "Buttons/Btn_ok.bmp");
+        assert_eq!(buttons[1].path, "Buttons/Dialogs");
+        assert!(tree.read_dir("test.bmp").is_none());
+        assert!(tree.read_dir("missing").is_none());
+    }
+
+    #[test]
+    fn test_is_dir() {
+        let tree = far().tree();
+        assert!(tree.is_dir(""));
+        assert!(tree.is_dir("buttons\\dialogs"));
+        assert!(!tree.is_dir("test.bmp"));
+        assert!(tree.is_file("Buttons/dialogs/FRAME.bmp"));
+        assert!(!tree.is_file("Buttons"));
+    }
+
+    #[test]
+    fn test_files() {
+        let paths: Vec<String> = far().tree().files().into_iter().map(|e| e.path).collect();
+        assert_eq!(
+            paths,
+            [
+                "Buttons/Btn_ok.bmp",
+                "Buttons/Dialogs/frame.bmp",
+                "test.bmp"
+            ]
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use crate::writer::HEADER_LENGTH;
+use crate::{normalize_name, Far, FarError, FarVariant};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::SeekFrom::End;
+use thiserror::Error;
+
+/// The problems found by [`Far::verify`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct VerifyReport {
+    /// The length of the far file in bytes.
+    pub file_length: u64,
+    /// The problems, in the order they were found.
+    pub problems: Vec<Problem>,
+}
+
+impl VerifyReport {
+    /// Whether no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// A structural problem in a far file. Entries are identified by their index in
+/// `manifest.manifest_entries` and their file name.
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Problem {
+    #[error("{name:?} at offset {offset} with length {len} extends past the end of the file")]
+    OutOfBounds {
+        entry: usize,
+        name: String,
+        offset: u64,
+        len: u64,
+    },
+    #[error("{name:?} at offset {offset} overlaps the header")]
+    OverlapsHeader {
+        entry: usize,
+        name: String,
+        offset: u64,
+    },
+    #[error("{name:?} at offset {offset} with length {len} overlaps the manifest")]
+    OverlapsManifest {
+        entry: usize,
+        name: String,
+        offset: u64,
+        len: u64,
+    },
+    #[error("{name:?} overlaps {other_name:?} at offset {offset} for {len} bytes")]
+    Overlap {
+        entry: usize,
+        name: String,
+        other: usize,
+        other_name: String,
+        offset: u64,
+        len: u64,
+    },
+    #[error("{len} bytes at offset {offset} don't belong to any file")]
+    Gap { offset: u64, len: u64 },
+    #[error("{name:?} is already in the far file as entry {first}")]
+    DuplicateName {
+        entry: usize,
+        name: String,
+        first: usize,
+    },
+    #[error("{name:?} has different file lengths {file_length1} and {file_length2}")]
+    LengthMismatch {
+        entry: usize,
+        name: String,
+        file_length1: u32,
+        file_length2: u32,
+    },
+    #[error("the manifest says it has {number_of_files} files but {manifest_entries} were read")]
+    CountMismatch {
+        number_of_files: u32,
+        manifest_entries: usize,
+    },
+    #[error("{len} bytes at offset {offset} follow the manifest")]
+    TrailingBytes { offset: u64, len: u64 },
+}
+
+impl Far {
+    /// Check the layout of the far file: that every file lies between the header and the
+    /// manifest without overlapping another, that nothing but the manifest follows the files,
+    /// that names are unique and that the manifest is consistent. Parsing only rejects problems
+    /// that stop files being read, so this catches archives that parse but are still broken.
+    ///
+    /// Gaps that only pad a file to a four-byte boundary aren't reported.
+    pub fn verify(&self) -> Result<VerifyReport, FarError> {
+        let file_length = self.source.lock()?.seek(End(0))?;
+        let manifest_offset = self.manifest_offset as u64;
+        let manifest_end = manifest_offset + self.manifest_length();
+        let entries = &self.manifest.manifest_entries;
+        let mut problems: Vec<Problem> = vec![];
+
+        // entries that point outside the space between the header and the manifest
+        for (i, me) in entries.iter().enumerate() {
+            let (offset, len) = (me.file_offset as u64, me.stored_length() as u64);
+            if offset + len > file_length {
+                problems.push(Problem::OutOfBounds {
+                    entry: i,
+                    name: me.file_name.clone(),
+                    offset,
+                    len,
+                });
+            }
+            if len > 0 && offset < HEADER_LENGTH as u64 {
+                problems.push(Problem::OverlapsHeader {
+                    entry: i,
+                    name: me.file_name.clone(),
+                    offset,
+                });
+            }
+            if len > 0 && offset < manifest_end && manifest_offset < offset + len {
+                problems.push(Problem::OverlapsManifest {
+                    entry: i,
+                    name: me.file_name.clone(),
+                    offset,
+                    len,
+                });
+            }
+        }
+
+        // overlaps and gaps, walking the files in offset order
+        let mut order: Vec<usize> = (0..entries.len())
+            .filter(|i| entries[*i].stored_length() > 0)
+            .collect();
+        order.sort_by_key(|i| (entries[*i].file_offset, entries[*i].stored_length()));
+        // the entry reaching furthest so far, and where it ends
+        let mut furthest: Option<usize> = None;
+        let mut end = HEADER_LENGTH as u64;
+        for i in order {
+            let me = &entries[i];
+            let offset = me.file_offset as u64;
+            let len = me.stored_length() as u64;
+            match furthest {
+                Some(other) if offset < end => problems.push(Problem::Overlap {
+                    entry: i,
+                    name: me.file_name.clone(),
+                    other,
+                    other_name: entries[other].file_name.clone(),
+                    offset,
+                    len: end.min(offset + len) - offset,
+                }),
+                _ if offset > end && !is_padding(end, offset) => problems.push(Problem::Gap {
+                    offset: end,
+                    len: offset - end,
+                }),
+                _ => {}
+            }
+            if offset + len > end {
+                furthest = Some(i);
+                end = offset + len;
+            }
+        }
+        if manifest_offset > end && !is_padding(end, manifest_offset) {
+            problems.push(Problem::Gap {
+                offset: end,
+                len: manifest_offset - end,
+            });
+        }
+
+        // names the game can't tell apart
+        let mut first: HashMap<String, usize> = HashMap::with_capacity(entries.len());
+        for (i, me) in entries.iter().enumerate() {
+            match first.get(&normalize_name(&me.file_name)) {
+                Some(first) => problems.push(Problem::DuplicateName {
+                    entry: i,
+                    name: me.file_name.clone(),
+                    first: *first,
+                }),
+                None => {
+                    first.insert(normalize_name(&me.file_name), i);
+                }
+            }
+        }
+
+        // compressed files store their compressed size in file_length2
+        for (i, me) in entries.iter().enumerate() {
+            if !me.is_compressed() && me.file_length1 != me.file_length2 {
+                problems.push(Problem::LengthMismatch {
+                    entry: i,
+                    name: me.file_name.clone(),
+                    file_length1: me.file_length1,
+                    file_length2: me.file_length2,
+                });
+            }
+        }
+
+        // where the manifest ends is only known when every entry was read
+        if self.manifest.number_of_files as usize != entries.len() {
+            problems.push(Problem::CountMismatch {
+                number_of_files: self.manifest.number_of_files,
+                manifest_entries: entries.len(),
+            });
+        } else if manifest_end < file_length {
+            problems.push(Problem::TrailingBytes {
+                offset: manifest_end,
+                len: file_length - manifest_end,
+            });
+        }
+
+        Ok(VerifyReport {
+            file_length,
+            problems,
+        })
+    }
+
+    /// The number of bytes the manifest takes up, counting the entries that were read.
+    fn manifest_length(&self) -> u64 {
+        let fixed = match self.variant {
+            FarVariant::V1a => 14,
+            FarVariant::V1b => 16,
+            FarVariant::V3 => 24,
+        };
+        let entries = &self.manifest.manifest_entries;
+        4 + entries
+            .iter()
+            .map(|me| fixed + me.file_name_bytes.len() as u64)
+            .sum::<u64>()
+    }
+}
+
+/// Whether the bytes from `start` to `end` only pad to a four-byte boundary.
+fn is_padding(start: u64, end: u64) -> bool {
+    end - start < 4 && end.is_multiple_of(4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::test_far_bytes;
+    use crate::ParseOptions;
+
+    fn lenient() -> ParseOptions {
+        ParseOptions {
+            strict: false,
+            ..ParseOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let far = Far::new("test.far").unwrap();
+        let report = far.verify().unwrap();
+        assert_eq!(report.file_length, 188);
+        assert!(report.is_ok(), "{:?}", report.problems);
+
+        let mut buf = test_far_bytes(&[("a.bmp", b"abcd"), ("b.bmp", b"efgh"), ("A.BMP", b"ijkl")]);
+        let report = Far::from_bytes(&buf).unwrap().verify().unwrap();
+        assert_eq!(report.problems.len(), 1);
+
+        // point b.bmp at a.bmp, leaving a gap where it was, and give a.bmp different lengths
+        let manifest_offset = u32::from_le_bytes(buf[12..16].try_into().unwrap()) as usize;
+        let a = manifest_offset + 4;
+        let b = a + 16 + 5;
+        buf[a + 4..a + 8].copy_from_slice(&5u32.to_le_bytes());
+        buf[b + 8..b + 12].copy_from_slice(&16u32.to_le_bytes());
+        buf.extend_from_slice(b"junk");
+        let far = Far::from_reader_with_options(std::io::Cursor::new(buf), &lenient()).unwrap();
+        let problems = far.verify().unwrap().problems;
+        assert_eq!(
+            problems,
+            [
+                Problem::Overlap {
+                    entry: 1,
+                    name: "b.bmp".to_string(),
+                    other: 0,
+                    other_name: "a.bmp".to_string(),
+                    offset: 16,
+                    len: 4,
+                },
+                Problem::Gap { offset: 20, len: 4 },
+                Problem::DuplicateName {
+                    entry: 2,
+                    name: "A.BMP".to_string(),
+                    first: 0,
+                },
+                Problem::LengthMismatch {
+                    entry: 0,
+                    name: "a.bmp".to_string(),
+                    file_length1: 4,
+                    file_length2: 5,
+                },
+                Problem::TrailingBytes {
+                    offset: manifest_offset as u64 + 67,
+                    len: 4,
+                },
+            ]
+        );
+    }
+}
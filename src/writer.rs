@@ -0,0 +1,253 @@
+use crate::{cp1252, Far, FarError, FarVariant};
+use std::fs;
+use std::io;
+use std::io::Write;
+
+/// The size of the header in bytes: the signature, the version and the manifest offset.
+pub(crate) const HEADER_LENGTH: u32 = 16;
+
+/// Builds a new far file from a list of named files.
+///
+/// Files are written in the order they were added. The contents are concatenated directly after
+/// the header and the manifest is written last, which matches the layout of the archives shipped
+/// with the game.
+#[derive(Clone)]
+pub struct FarWriter {
+    entries: Vec<WriterEntry>,
+    variant: FarVariant,
+}
+
+#[derive(Clone)]
+struct WriterEntry {
+    file_name: Vec<u8>,
+    bytes: Vec<u8>,
+}
+
+impl FarWriter {
+    /// Create a new, empty FarWriter that writes the 1b variant.
+    pub fn new() -> FarWriter {
+        FarWriter {
+            entries: vec![],
+            variant: FarVariant::V1b,
+        }
+    }
+
+    /// Set the manifest entry layout. Only the version one variants can be written.
+    pub fn variant(&mut self, variant: FarVariant) -> &mut FarWriter {
+        self.variant = variant;
+        self
+    }
+
+    /// Add a file to the archive from a buffer. The file name can include directories. It is
+    /// stored as Windows-1252 like the game expects, or as UTF-8 if it has characters that
+    /// Windows-1252 can't represent.
+    pub fn add(&mut self, file_name: &str, bytes: Vec<u8>) -> &mut FarWriter {
+        self.add_raw(cp1252::encode(file_name), bytes)
+    }
+
+    /// Add a file to the archive from a buffer, storing the file name bytes as they are.
+    pub fn add_raw(&mut self, file_name: Vec<u8>, bytes: Vec<u8>) -> &mut FarWriter {
+        self.entries.push(WriterEntry { file_name, bytes });
+        self
+    }
+
+    /// Add a file to the archive by reading it from disk. The file is stored under `file_name`,
+    /// not under `path`.
+    pub fn add_file(&mut self, file_name: &str, path: &str) -> Result<&mut FarWriter, FarError> {
+        let bytes = fs::read(path)?;
+        Ok(self.add(file_name, bytes))
+    }
+
+    /// Write the archive to `w`.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<(), FarError> {
+        if self.variant == FarVariant::V3 {
+            return Err(FarError::FileError(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "writing version three far files is not supported",
+            )));
+        }
+
+        // lay out the file offsets
+        let mut offsets: Vec<u32> = Vec::with_capacity(self.entries.len());
+        let mut manifest_offset = HEADER_LENGTH;
+        for entry in &self.entries {
+            offsets.push(manifest_offset);
+            manifest_offset = manifest_offset
+                .checked_add(to_u32(entry.bytes.len())?)
+                .ok_or_else(too_large)?;
+        }
+
+        // write header
+        w.write_all(b"FAR!byAZ")?;
+        w.write_all(&1u32.to_le_bytes())?;
+        w.write_all(&manifest_offset.to_le_bytes())?;
+
+        // write files
+        for entry in &self.entries {
+            w.write_all(&entry.bytes)?;
+        }
+
+        // write manifest
+        let records: Vec<ManifestRecord> = self
+            .entries
+            .iter()
+            .zip(offsets)
+            .map(|(entry, file_offset)| {
+                let file_length = to_u32(entry.bytes.len())?;
+                Ok(ManifestRecord {
+                    file_name: &entry.file_name,
+                    file_length1: file_length,
+                    file_length2: file_length,
+                    file_offset,
+                })
+            })
+            .collect::<Result<_, FarError>>()?;
+        write_manifest(w, self.variant, &records)
+    }
+
+    /// Write the archive to a new file at `path`, replacing it if it exists.
+    pub fn write_to_path(&self, path: &str) -> Result<(), FarError> {
+        let mut f = io::BufWriter::new(fs::File::create(path)?);
+        self.write_to(&mut f)?;
+        f.flush()?;
+        Ok(())
+    }
+}
+
+impl Default for FarWriter {
+    fn default() -> FarWriter {
+        FarWriter::new()
+    }
+}
+
+impl Far {
+    /// Write the archive to `w`, reading the contents of every entry from the original far file.
+    /// Entries keep their order, name bytes and manifest layout. Version three archives are
+    /// written as 1b archives with every file decompressed.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<(), FarError> {
+        let mut writer = FarWriter::new();
+        if self.variant == FarVariant::V1a {
+            writer.variant(FarVariant::V1a);
+        }
+        for me in &self.manifest.manifest_entries {
+            writer.add_raw(me.file_name_bytes.clone(), me.get_bytes()?);
+        }
+        writer.write_to(w)
+    }
+}
+
+/// A version one manifest entry to write.
+pub(crate) struct ManifestRecord<'a> {
+    pub(crate) file_name: &'a [u8],
+    pub(crate) file_length1: u32,
+    pub(crate) file_length2: u32,
+    pub(crate) file_offset: u32,
+}
+
+/// Write a version one manifest: the number of files followed by an entry for each file.
+pub(crate) fn write_manifest<W: Write>(
+    w: &mut W,
+    variant: FarVariant,
+    records: &[ManifestRecord],
+) -> Result<(), FarError> {
+    w.write_all(&to_u32(records.len())?.to_le_bytes())?;
+    for record in records {
+        w.write_all(&record.file_length1.to_le_bytes())?;
+        w.write_all(&record.file_length2.to_le_bytes())?;
+        w.write_all(&record.file_offset.to_le_bytes())?;
+        let file_name_length = to_u32(record.file_name.len())?;
+        if variant == FarVariant::V1a {
+            let file_name_length = u16::try_from(file_name_length).map_err(|_| {
+                FarError::FileError(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "1a far files cannot have filenames longer than 65535 bytes",
+                ))
+            })?;
+            w.write_all(&file_name_length.to_le_bytes())?;
+        } else {
+            w.write_all(&file_name_length.to_le_bytes())?;
+        }
+        w.write_all(record.file_name)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn to_u32(n: usize) -> Result<u32, FarError> {
+    u32::try_from(n).map_err(|_| too_large())
+}
+
+pub(crate) fn too_large() -> FarError {
+    FarError::FileError(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "far files cannot be larger than 4 GiB",
+    ))
+}
+
+/// Pack `files` into a far file in memory, for tests.
+#[cfg(test)]
+pub(crate) fn test_far_bytes(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = FarWriter::new();
+    for (name, bytes) in files {
+        writer.add(name, bytes.to_vec());
+    }
+    let mut buf: Vec<u8> = vec![];
+    writer.write_to(&mut buf).unwrap();
+    buf
+}
+
+/// Pack `files` into a far file in memory and parse it, for tests.
+#[cfg(test)]
+pub(crate) fn test_far(files: &[(&str, &[u8])]) -> Far {
+    Far::from_bytes(&test_far_bytes(files)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let far = Far::new("test.far").unwrap();
+        let mut buf: Vec<u8> = vec![];
+        far.write_to(&mut buf).unwrap();
+        assert_eq!(buf, fs::read("test.far").unwrap());
+    }
+
+    #[test]
+    fn test_1a_round_trip() {
+        let mut writer = FarWriter::new();
+        writer.variant(FarVariant::V1a);
+        writer.add("a.txt", b"abcd".to_vec());
+        let mut buf: Vec<u8> = vec![];
+        writer.write_to(&mut buf).unwrap();
+        assert_eq!(buf.len(), 16 + 4 + 4 + 14 + 5);
+
+        let far = Far::from_bytes(&buf).unwrap();
+        assert_eq!(far.variant, FarVariant::V1a);
+        let mut rewritten: Vec<u8> = vec![];
+        far.write_to(&mut rewritten).unwrap();
+        assert_eq!(rewritten, buf);
+    }
+
+    #[test]
+    fn test_write_layout() {
+        let mut writer = FarWriter::new();
+        writer.add("a.txt", b"abcd".to_vec());
+        writer.add("dir/b.txt", b"efghij".to_vec());
+        let mut buf: Vec<u8> = vec![];
+        writer.write_to(&mut buf).unwrap();
+
+        assert_eq!(&buf[0..8], b"FAR!byAZ");
+        assert_eq!(&buf[8..12], &1u32.to_le_bytes());
+        assert_eq!(&buf[12..16], &26u32.to_le_bytes());
+        assert_eq!(&buf[16..26], b"abcdefghij");
+        assert_eq!(&buf[26..30], &2u32.to_le_bytes());
+        // second entry: lengths, offset, name length, name
+        let second = &buf[30 + 16 + 5..];
+        assert_eq!(&second[0..4], &6u32.to_le_bytes());
+        assert_eq!(&second[4..8], &6u32.to_le_bytes());
+        assert_eq!(&second[8..12], &20u32.to_le_bytes());
+        assert_eq!(&second[12..16], &9u32.to_le_bytes());
+        assert_eq!(&second[16..], b"dir/b.txt");
+    }
+}